@@ -0,0 +1,287 @@
+//! A token stream flattened into a buffer which can be walked with cheap,
+//! copyable cursors.
+//!
+//! Iterating a `TokenStream` hands out owned token trees, so looking ahead
+//! means collecting them somewhere and looking inside a group means taking
+//! its nested stream apart too. A `TokenBuffer` does that work once, up
+//! front, after which a `Cursor` can peek any distance ahead, step into and
+//! out of groups, and be saved and restored just by copying it.
+
+use std::mem;
+
+use {Delimiter, Group, Lifetime, Literal, Spacing, Span, Term, TokenNode, TokenStream, TokenTree};
+
+enum Entry {
+    // The span of the tree holding the group, the group with its stream left
+    // empty, since its contents follow as entries of their own, and the index
+    // of the group's `End` entry.
+    Group(Span, Group, usize),
+    Term(Span, Term),
+    Lifetime(Span, Lifetime),
+    Op(Span, char, Spacing),
+    Literal(Span, Literal),
+    // The index of the opening `Group` entry, if this ends a group rather
+    // than the whole buffer, followed by the index of the `End` entry of the
+    // scope containing that group.
+    End(Option<usize>, usize),
+}
+
+/// An immutable, flattened copy of a `TokenStream`.
+pub struct TokenBuffer {
+    entries: Vec<Entry>,
+}
+
+impl TokenBuffer {
+    /// Moves the token trees of `stream` into a new buffer.
+    pub fn new(stream: TokenStream) -> TokenBuffer {
+        let mut entries = Vec::new();
+        let groups = flatten(&mut entries, stream);
+        let end = entries.len();
+        entries.push(Entry::End(None, end));
+        set_outer(&mut entries, &groups, end);
+        TokenBuffer { entries: entries }
+    }
+
+    /// A cursor pointing at the first token in the buffer.
+    pub fn begin(&self) -> Cursor {
+        Cursor {
+            entries: &self.entries,
+            pos: 0,
+            end: self.entries.len() - 1,
+        }
+    }
+}
+
+// Appends the entries for `stream`, returning the indices of the `End`
+// entries of its top-level groups so that the caller can fill in where they
+// exit to.
+fn flatten(entries: &mut Vec<Entry>, stream: TokenStream) -> Vec<usize> {
+    let mut groups = Vec::new();
+    for tt in stream {
+        let span = tt.span;
+        match tt.kind {
+            TokenNode::Group(mut group) => {
+                let inner = mem::replace(&mut group.stream, TokenStream::empty());
+                let open = entries.len();
+                entries.push(Entry::End(None, 0));
+                let nested = flatten(entries, inner);
+                let end = entries.len();
+                entries.push(Entry::End(Some(open), 0));
+                set_outer(entries, &nested, end);
+                entries[open] = Entry::Group(span, group, end);
+                groups.push(end);
+            }
+            TokenNode::Term(term) => entries.push(Entry::Term(span, term)),
+            TokenNode::Lifetime(lifetime) => entries.push(Entry::Lifetime(span, lifetime)),
+            TokenNode::Op(ch, spacing) => entries.push(Entry::Op(span, ch, spacing)),
+            TokenNode::Literal(lit) => entries.push(Entry::Literal(span, lit)),
+        }
+    }
+    groups
+}
+
+fn set_outer(entries: &mut [Entry], ends: &[usize], outer: usize) {
+    for &end in ends {
+        if let Entry::End(open, _) = entries[end] {
+            entries[end] = Entry::End(open, outer);
+        }
+    }
+}
+
+/// A position within a `TokenBuffer`, restricted to one level of nesting.
+///
+/// Cursors are `Copy`, so recording a position to backtrack to is just a
+/// matter of keeping the old cursor around. Every method which moves forward
+/// returns a new cursor and leaves `self` untouched.
+#[derive(Copy, Clone)]
+pub struct Cursor<'a> {
+    entries: &'a [Entry],
+    pos: usize,
+    // The index of the `End` entry for the current group or buffer.
+    end: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn entry(self) -> &'a Entry {
+        &self.entries[self.pos]
+    }
+
+    fn bump(self) -> Cursor<'a> {
+        let pos = match *self.entry() {
+            Entry::Group(_, _, end) => end + 1,
+            _ => self.pos + 1,
+        };
+        Cursor {
+            entries: self.entries,
+            pos: pos,
+            end: self.end,
+        }
+    }
+
+    /// Whether there are no tokens left in the current group or buffer.
+    pub fn eof(self) -> bool {
+        self.pos == self.end
+    }
+
+    /// Moves past the next token tree, treating a group as a single tree.
+    pub fn skip(self) -> Option<Cursor<'a>> {
+        if self.eof() {
+            None
+        } else {
+            Some(self.bump())
+        }
+    }
+
+    /// Moves `n` token trees forward, for looking past the next token without
+    /// consuming anything.
+    pub fn advance(self, n: usize) -> Option<Cursor<'a>> {
+        let mut cursor = self;
+        for _ in 0..n {
+            cursor = cursor.skip()?;
+        }
+        Some(cursor)
+    }
+
+    /// If the next token is a group delimited by `delim`, returns a cursor
+    /// into its contents, the span of the group and a cursor past it.
+    pub fn group(self, delim: Delimiter) -> Option<(Cursor<'a>, Span, Cursor<'a>)> {
+        match self.any_group() {
+            Some((inside, d, span, after)) if d == delim => Some((inside, span, after)),
+            _ => None,
+        }
+    }
+
+    /// Like `group`, but accepting any delimiter and returning it.
+    pub fn any_group(self) -> Option<(Cursor<'a>, Delimiter, Span, Cursor<'a>)> {
+        if self.eof() {
+            return None;
+        }
+        match *self.entry() {
+            Entry::Group(span, ref group, end) => {
+                let inside = Cursor {
+                    entries: self.entries,
+                    pos: self.pos + 1,
+                    end: end,
+                };
+                Some((inside, group.delimiter, span, self.bump()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Leaves the current group, returning a cursor to the token after it.
+    ///
+    /// Any tokens not yet consumed within the group are skipped. Returns
+    /// `None` at the top level of the buffer.
+    pub fn exit(self) -> Option<Cursor<'a>> {
+        match self.entries[self.end] {
+            Entry::End(Some(_), outer) => {
+                Some(Cursor {
+                    entries: self.entries,
+                    pos: self.end + 1,
+                    end: outer,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn term(self) -> Option<(&'a Term, Span, Cursor<'a>)> {
+        if self.eof() {
+            return None;
+        }
+        match *self.entry() {
+            Entry::Term(span, ref term) => Some((term, span, self.bump())),
+            _ => None,
+        }
+    }
+
+    pub fn lifetime(self) -> Option<(&'a Lifetime, Span, Cursor<'a>)> {
+        if self.eof() {
+            return None;
+        }
+        match *self.entry() {
+            Entry::Lifetime(span, ref lifetime) => Some((lifetime, span, self.bump())),
+            _ => None,
+        }
+    }
+
+    pub fn op(self) -> Option<(char, Spacing, Span, Cursor<'a>)> {
+        if self.eof() {
+            return None;
+        }
+        match *self.entry() {
+            Entry::Op(span, ch, spacing) => Some((ch, spacing, span, self.bump())),
+            _ => None,
+        }
+    }
+
+    pub fn literal(self) -> Option<(&'a Literal, Span, Cursor<'a>)> {
+        if self.eof() {
+            return None;
+        }
+        match *self.entry() {
+            Entry::Literal(span, ref lit) => Some((lit, span, self.bump())),
+            _ => None,
+        }
+    }
+
+    /// Copies out the next token tree, rebuilding the stream of a group.
+    pub fn token_tree(self) -> Option<(TokenTree, Cursor<'a>)> {
+        if self.eof() {
+            return None;
+        }
+        let kind = match *self.entry() {
+            Entry::Group(_, ref group, _) => {
+                let (inside, _, _, _) = self.any_group().unwrap();
+                TokenNode::Group(Group {
+                    stream: inside.token_stream(),
+                    ..group.clone()
+                })
+            }
+            Entry::Term(_, ref term) => TokenNode::Term(term.clone()),
+            Entry::Lifetime(_, ref lifetime) => TokenNode::Lifetime(lifetime.clone()),
+            Entry::Op(_, ch, spacing) => TokenNode::Op(ch, spacing),
+            Entry::Literal(_, ref lit) => TokenNode::Literal(lit.clone()),
+            Entry::End(..) => unreachable!(),
+        };
+        let tree = TokenTree { span: self.span(), kind: kind };
+        Some((tree, self.bump()))
+    }
+
+    /// Copies out all of the remaining token trees at this level.
+    pub fn token_stream(self) -> TokenStream {
+        let mut trees = Vec::new();
+        let mut cursor = self;
+        while let Some((tt, rest)) = cursor.token_tree() {
+            trees.push(tt);
+            cursor = rest;
+        }
+        trees.into_iter().collect()
+    }
+
+    /// The span of the next token, or at the end of a group the span of the
+    /// whole group.
+    pub fn span(self) -> Span {
+        match *self.entry() {
+            Entry::Group(span, _, _) |
+            Entry::Term(span, _) |
+            Entry::Lifetime(span, _) |
+            Entry::Op(span, _, _) |
+            Entry::Literal(span, _) => span,
+            Entry::End(Some(open), _) => {
+                match self.entries[open] {
+                    Entry::Group(span, _, _) => span,
+                    _ => unreachable!(),
+                }
+            }
+            Entry::End(None, _) => Span::call_site(),
+        }
+    }
+}
+
+impl<'a> PartialEq for Cursor<'a> {
+    fn eq(&self, other: &Cursor) -> bool {
+        self.entries.as_ptr() == other.entries.as_ptr() && self.pos == other.pos
+    }
+}
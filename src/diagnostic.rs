@@ -0,0 +1,240 @@
+use std::fmt;
+use std::iter::FromIterator;
+use std::slice;
+use std::vec;
+
+use imp;
+use {Delimiter, Group, Literal, Span, Spacing, Term, TokenNode, TokenStream, TokenTree};
+
+/// The severity of a `Diagnostic`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+        })
+    }
+}
+
+/// A message for the user of a procedural macro, pointing at a span of their
+/// input, along with any number of attached notes.
+///
+/// With the `unstable` feature the diagnostic is handed to the compiler when
+/// emitted. On stable there is no such channel, so errors are instead lowered
+/// into `compile_error!` invocations which the macro has to include in its
+/// output.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    level: Level,
+    message: String,
+    span: Span,
+    children: Vec<Diagnostic>,
+}
+
+macro_rules! diagnostic_child_methods {
+    ($spanned:ident, $regular:ident, $level:expr) => (
+        /// Adds a child diagnostic at this level pointing at `span`.
+        pub fn $spanned<T: Into<String>>(mut self, span: Span, message: T) -> Diagnostic {
+            self.children.push(Diagnostic::spanned(span, $level, message));
+            self
+        }
+
+        /// Adds a child diagnostic at this level with no span of its own.
+        pub fn $regular<T: Into<String>>(mut self, message: T) -> Diagnostic {
+            self.children.push(Diagnostic::new($level, message));
+            self
+        }
+    )
+}
+
+impl Diagnostic {
+    pub fn new<T: Into<String>>(level: Level, message: T) -> Diagnostic {
+        Diagnostic::spanned(Span::call_site(), level, message)
+    }
+
+    pub fn spanned<T: Into<String>>(span: Span, level: Level, message: T) -> Diagnostic {
+        Diagnostic {
+            level: level,
+            message: message.into(),
+            span: span,
+            children: Vec::new(),
+        }
+    }
+
+    diagnostic_child_methods!(span_error, error, Level::Error);
+    diagnostic_child_methods!(span_warning, warning, Level::Warning);
+    diagnostic_child_methods!(span_note, note, Level::Note);
+    diagnostic_child_methods!(span_help, help, Level::Help);
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn children(&self) -> &[Diagnostic] {
+        &self.children
+    }
+
+    /// Emits this diagnostic.
+    ///
+    /// With the `unstable` feature the diagnostic goes straight to the
+    /// compiler and an empty stream is returned. On stable the result of
+    /// `to_compile_error` is returned, and must be spliced into the macro's
+    /// output for the user to see it.
+    pub fn emit(self) -> TokenStream {
+        imp::emit_diagnostic(self)
+    }
+
+    /// Renders this diagnostic as a `compile_error! { "..." }` invocation
+    /// whose tokens all carry the diagnostic's span.
+    ///
+    /// Only errors have a representation: `compile_error!` cannot produce
+    /// warnings, so any other level yields an empty stream. Child
+    /// diagnostics are appended to the message, one per line.
+    pub fn to_compile_error(&self) -> TokenStream {
+        if self.level != Level::Error {
+            return TokenStream::empty();
+        }
+
+        let mut message = self.message.clone();
+        for child in &self.children {
+            message.push_str(&format!("\n{}: {}", child.level, child.message));
+        }
+
+        let span = self.span;
+        let token = |kind| TokenTree { span: span, kind: kind };
+        let args = TokenStream::from(token(TokenNode::Literal(Literal::string(&message))));
+        let mut group = Group::new(Delimiter::Brace, args);
+        group.set_span(span);
+        vec![
+            token(TokenNode::Term(Term::intern("compile_error"))),
+            token(TokenNode::Op('!', Spacing::Alone)),
+            token(TokenNode::Group(group)),
+        ].into_iter().collect()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.level, self.message)?;
+        for child in &self.children {
+            write!(f, "\n{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates diagnostics while a macro walks its input, so that every
+/// problem can be reported at once instead of one per compile.
+#[derive(Clone, Debug, Default)]
+pub struct Errors {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Errors {
+    pub fn new() -> Errors {
+        Errors { diagnostics: Vec::new() }
+    }
+
+    pub fn push(&mut self, diag: Diagnostic) {
+        self.diagnostics.push(diag);
+    }
+
+    /// Records an error with `message` pointing at `span`.
+    pub fn error<T: Into<String>>(&mut self, span: Span, message: T) {
+        self.push(Diagnostic::spanned(span, Level::Error, message));
+    }
+
+    /// Moves all of the diagnostics collected in `other` into `self`.
+    pub fn combine(&mut self, other: Errors) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// Returns `Ok(())` if nothing was collected, and `self` otherwise, for
+    /// bailing out with `?` after a validation pass.
+    pub fn finish(self) -> Result<(), Errors> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Renders every collected error as consecutive `compile_error!`
+    /// invocations in a single stream.
+    pub fn to_compile_errors(&self) -> TokenStream {
+        self.iter().map(Diagnostic::to_compile_error).collect()
+    }
+
+    /// Emits every collected diagnostic in order, returning whatever tokens
+    /// the backend needs to be included in the macro's output (see
+    /// `Diagnostic::emit`).
+    pub fn emit(self) -> TokenStream {
+        self.into_iter().map(Diagnostic::emit).collect()
+    }
+}
+
+impl From<Diagnostic> for Errors {
+    fn from(diag: Diagnostic) -> Errors {
+        Errors { diagnostics: vec![diag] }
+    }
+}
+
+impl Extend<Diagnostic> for Errors {
+    fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, iter: I) {
+        self.diagnostics.extend(iter);
+    }
+}
+
+impl FromIterator<Diagnostic> for Errors {
+    fn from_iter<I: IntoIterator<Item = Diagnostic>>(iter: I) -> Errors {
+        Errors { diagnostics: iter.into_iter().collect() }
+    }
+}
+
+impl IntoIterator for Errors {
+    type Item = Diagnostic;
+    type IntoIter = vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> vec::IntoIter<Diagnostic> {
+        self.diagnostics.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Errors {
+    type Item = &'a Diagnostic;
+    type IntoIter = slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> slice::Iter<'a, Diagnostic> {
+        self.iter()
+    }
+}
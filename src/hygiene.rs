@@ -0,0 +1,42 @@
+//! Hygiene for names spanned with `Span::def_site`.
+//!
+//! A procedural macro often needs helper variables of its own, and with the
+//! compiler's hygiene those are given a def_site span so that they can't
+//! clash with the user's names. On stable the output is handed to the
+//! compiler as a string, and the spans are lost along with the hygiene.
+//!
+//! To make up for it, the stable backend gives the macro's local variables
+//! fresh names when a `TokenStream` is converted into a
+//! `proc_macro::TokenStream`. A local variable here is a def_site name which
+//! makes up a whole pattern somewhere in the stream, as in `let x`,
+//! `for x in`, `fn f(mut x: u8)` or `ref x`, and every def_site use of it is
+//! renamed the same way. The same name after `.` or `::`, as in `v.len()`,
+//! or naming a field in `S { x: 1 }`, is a member rather than the variable
+//! and is kept, while a field shorthand `S { x }` is written out as
+//! `S { x: __h0_x }`. Names bound within larger patterns, such as
+//! `let (a, b)`, `if let Some(x)` or the parameters of a closure `|x|`,
+//! can't be told apart from paths by their tokens alone and are left as
+//! they are. Lifetimes and labels with a def_site span are renamed when the
+//! stream declares them, in the generics of an item, an `impl` or a
+//! `for<...>`, or as the label of a loop or block; others, such as
+//! `'static` and `'_`, are kept. New names are chosen not to clash with any
+//! name in the stream.
+//!
+//! Other def_site names, such as types, functions and paths, are left as
+//! they are: renaming them would stop them from resolving to the items the
+//! macro meant. Tokens spanned with `call_site` or `mixed_site` are left
+//! alone too, so this only affects macros which ask for it.
+
+use imp;
+use TokenStream;
+
+/// Applies the renaming described in the module documentation to `stream`
+/// right away, for macros which print their output rather than returning
+/// it to the compiler.
+///
+/// Each call is a separate expansion, so the same def_site name is renamed
+/// differently in separate calls. With the `unstable` feature the compiler
+/// provides real hygiene and the stream is returned unchanged.
+pub fn resolve(stream: TokenStream) -> TokenStream {
+    imp::resolve_hygiene(stream)
+}
@@ -0,0 +1,174 @@
+//! Keyword tables and identifier rules shared by both backends.
+
+use std::error::Error;
+use std::fmt;
+
+use unicode_xid::UnicodeXID;
+
+use Edition;
+
+/// How a word is reserved by the language, as returned by
+/// `Term::keyword_class`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum KeywordClass {
+    /// A keyword which can never be used as an identifier, such as `fn`.
+    Strict,
+    /// A word with no meaning yet which is set aside for future use, such
+    /// as `abstract`, and which also can't be used as an identifier.
+    Reserved,
+    /// A word which is only a keyword in certain contexts, such as `union`,
+    /// and is otherwise an ordinary identifier.
+    Weak,
+}
+
+// Keywords in every edition, from the reference.
+static STRICT_KEYWORDS: &'static [&'static str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+    "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct",
+    "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+];
+
+static STRICT_KEYWORDS_2018: &'static [&'static str] = &["async", "await", "dyn"];
+
+// Reserved for future use; these can't be used as identifiers either.
+static RESERVED_KEYWORDS: &'static [&'static str] = &[
+    "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+static RESERVED_KEYWORDS_2018: &'static [&'static str] = &["try"];
+
+static RESERVED_KEYWORDS_2024: &'static [&'static str] = &["gen"];
+
+static WEAK_KEYWORDS: &'static [&'static str] = &[
+    "auto", "default", "macro_rules", "raw", "safe", "union",
+];
+
+// Keywords which can't even be used as raw identifiers.
+static PATH_KEYWORDS: &'static [&'static str] = &["_", "crate", "self", "Self", "super"];
+
+pub fn keyword_class(s: &str, edition: Edition) -> Option<KeywordClass> {
+    let since = |e: Edition| edition >= e;
+    if STRICT_KEYWORDS.contains(&s) ||
+       (since(Edition::Edition2018) && STRICT_KEYWORDS_2018.contains(&s)) {
+        Some(KeywordClass::Strict)
+    } else if RESERVED_KEYWORDS.contains(&s) ||
+              (since(Edition::Edition2018) && RESERVED_KEYWORDS_2018.contains(&s)) ||
+              (since(Edition::Edition2024) && RESERVED_KEYWORDS_2024.contains(&s)) {
+        Some(KeywordClass::Reserved)
+    } else if WEAK_KEYWORDS.contains(&s) || s == "dyn" {
+        // `dyn` only became a strict keyword in 2018.
+        Some(KeywordClass::Weak)
+    } else {
+        None
+    }
+}
+
+/// Whether `s` is a strict or reserved keyword, and so can't be used as a
+/// plain identifier or lifetime name in `edition`.
+pub fn is_keyword(s: &str, edition: Edition) -> bool {
+    match keyword_class(s, edition) {
+        Some(KeywordClass::Strict) | Some(KeywordClass::Reserved) => true,
+        Some(KeywordClass::Weak) | None => false,
+    }
+}
+
+/// Whether `ch` can begin an identifier.
+pub fn is_ident_start(ch: char) -> bool {
+    UnicodeXID::is_xid_start(ch) || ch == '_'
+}
+
+/// Whether `ch` can appear in an identifier after the first character.
+pub fn is_ident_continue(ch: char) -> bool {
+    UnicodeXID::is_xid_continue(ch)
+}
+
+/// Whether `s` is made up of identifier characters, ignoring keywords.
+pub fn is_ident_chars(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(ch) if is_ident_start(ch) => {}
+        _ => return false,
+    }
+    chars.all(is_ident_continue)
+}
+
+/// Checks that `s` is made up of identifier characters, or is a raw
+/// identifier, as the lexer requires of terms.
+pub fn check_ident(s: &str) -> Result<(), TermError> {
+    let valid = if s.starts_with("r#") {
+        is_ident_chars(&s[2..]) && can_be_raw(&s[2..])
+    } else {
+        is_ident_chars(s)
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(TermError::new(s))
+    }
+}
+
+/// Whether `s` is a valid lifetime such as `'a` in the 2015 edition, which
+/// reserves the fewest keywords.
+pub fn is_lifetime(s: &str) -> bool {
+    if !s.starts_with('\'') {
+        return false;
+    }
+    let name = &s[1..];
+    is_ident_chars(name) && (name == "static" || !is_keyword(name, Edition::Edition2015))
+}
+
+/// Whether `s` is allowed after `r#`.
+pub fn can_be_raw(s: &str) -> bool {
+    !PATH_KEYWORDS.contains(&s)
+}
+
+/// Turns an arbitrary string into a valid identifier, returning whether it
+/// has to be used as a raw identifier.
+///
+/// Characters which can't appear in an identifier become `_`, and keywords
+/// which can't be raw identifiers get a `_` appended instead.
+pub fn to_ident(s: &str) -> (String, bool) {
+    let mut ident = s.chars()
+        .map(|ch| if is_ident_continue(ch) { ch } else { '_' })
+        .collect::<String>();
+    match ident.chars().next() {
+        Some(ch) if is_ident_start(ch) => {}
+        _ => ident.insert(0, '_'),
+    }
+    if !can_be_raw(&ident) {
+        ident.push('_');
+        (ident, false)
+    } else {
+        // Keywords only ever get added, so this covers every edition.
+        let raw = is_keyword(&ident, Edition::Edition2024);
+        (ident, raw)
+    }
+}
+
+/// The error returned by `Term::try_new` for a string which isn't an
+/// identifier.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TermError {
+    string: String,
+}
+
+impl TermError {
+    fn new(string: &str) -> TermError {
+        TermError { string: string.to_owned() }
+    }
+
+    /// The string which was rejected.
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+}
+
+impl fmt::Display for TermError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` is not a valid identifier", self.string)
+    }
+}
+
+impl Error for TermError {}
@@ -0,0 +1,59 @@
+//! Keeping track of the memory held by `Term`s and spans on stable.
+//!
+//! The stable backend interns the string of every `Term` it creates, and
+//! keeps a copy of every string it lexes, along with where its lines start,
+//! so that spans can report their line, column and source text. All of this
+//! is normally kept for the rest of the thread's life. That's fine for a
+//! procedural macro, but a long-running process which keeps lexing new code
+//! will see its memory grow by more than the size of everything it lexed.
+//! Such processes can do their work inside `with_interner`, which frees what
+//! was interned and lexed along the way.
+
+use imp;
+
+/// The size of the interner, as returned by `stats`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of distinct strings interned.
+    pub symbols: usize,
+    /// The total length in bytes of those strings, not counting the
+    /// interner's own bookkeeping.
+    pub bytes: usize,
+    /// The total length in bytes of strings which `with_interner` dropped
+    /// from the interner, but which are still held by terms created inside
+    /// it.
+    pub kept: usize,
+}
+
+/// Reports how much the interner holds.
+///
+/// Returns `None` with the `unstable` feature, where symbols are interned by
+/// the compiler.
+pub fn stats() -> Option<Stats> {
+    imp::interner_stats()
+}
+
+/// Runs `f`, then drops every symbol which was first interned while it was
+/// running from the interner, and frees the source of every string lexed by
+/// it.
+///
+/// Symbols which already existed when `f` started are kept. Calls may be
+/// nested, in which case each drops only what was interned within it.
+///
+/// A `Term` holds on to its own string, so terms created inside `f`, such as
+/// those in token streams lexed there, can go on being used after it
+/// returns; the string is freed along with the last of them. Interning the
+/// same name again afterwards makes a new symbol, which compares equal to
+/// the old one. Spans of tokens lexed inside `f` can still be used too, but
+/// behave like `Span::call_site()`.
+///
+/// With the `thread-safe` feature the interner is shared by every thread,
+/// and each call only drops the symbols interned by its own thread. The
+/// source map is shared in the same way, and other threads might still be
+/// using any of it, so no source is freed. With the `unstable` feature the
+/// compiler owns the symbols and the source, and nothing is freed at all.
+pub fn with_interner<F, R>(f: F) -> R
+    where F: FnOnce() -> R
+{
+    imp::with_interner(f)
+}
@@ -18,15 +18,21 @@
 //! enables it to reimplement itself with the unstable API of `proc_macro`.
 //! This'll allow immediate usage of the beneficial upstream API, particularly
 //! around preserving span information.
+//!
+//! On stable, tokens can't be sent between threads unless the `thread-safe`
+//! feature is enabled, which moves the interner for `Term`s and the table of
+//! parsed source behind global locks.
 
 #![cfg_attr(feature = "unstable", feature(proc_macro))]
 
 extern crate proc_macro;
 
-#[cfg(not(feature = "unstable"))]
 extern crate unicode_xid;
 
+use std::error::Error;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::iter::FromIterator;
 
@@ -44,11 +50,132 @@ mod imp;
 #[macro_use]
 mod macros;
 
-#[derive(Clone)]
+mod diagnostic;
+pub use diagnostic::{Diagnostic, Errors, Level};
+
+mod ident;
+pub use ident::{KeywordClass, TermError};
+
+mod lit;
+pub use lit::{LitError, LitErrorKind};
+
+mod pretty;
+
+pub mod buffer;
+pub mod hygiene;
+pub mod interner;
+pub mod op;
+pub mod parse;
+
+#[macro_use]
+mod quote;
+pub use quote::ToTokens;
+#[doc(hidden)]
+pub use quote::__rt;
+
+/// An abstract stream of token trees.
+///
+/// Streams compare and hash structurally, ignoring spans; see
+/// `eq_with_spans` for a comparison which takes them into account.
+///
+/// Printing a stream with `{}` and lexing the result gives back the same
+/// stream, except that groups with `Delimiter::None` have nothing to print
+/// and are flattened into their contents. The alternate form `{:#}` instead
+/// surrounds them with `/*«*/` and `/*»*/`, which `parse_alternate` reads
+/// back as an invisible group; to `FromStr` they are ordinary comments.
+///
+/// So `{:#}` and `parse_alternate` together give back any stream which came
+/// from the lexer, and any stream built by hand except for those that no
+/// text lexes to:
+///
+/// * A joint `/` followed by `/` or `*` would start a comment, so a space
+///   is printed between them and the `/` comes back `Spacing::Alone`.
+/// * Likewise any joint `Op` followed by something other than an `Op`,
+///   since the lexer only joins adjacent operator characters.
+/// * Negative numeric literals, such as `Literal::i32(-1)`, come back as a
+///   `-` followed by the literal.
+///
+/// With the `unstable` feature, printing is left to the compiler.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct TokenStream(imp::TokenStream);
 
 pub struct LexError(imp::LexError);
 
+impl LexError {
+    /// What went wrong while lexing.
+    pub fn kind(&self) -> LexErrorKind {
+        self.0.kind()
+    }
+
+    /// The token which the lexer was looking for when it failed, if any, such
+    /// as the `)` closing an unterminated group.
+    pub fn expected(&self) -> Option<&str> {
+        self.0.expected_token()
+    }
+
+    /// The location of the failure; `span().start()` gives its line and
+    /// column.
+    pub fn span(&self) -> Span {
+        Span(self.0.span())
+    }
+
+    /// The byte offset of the failure within the string being parsed.
+    pub fn offset(&self) -> usize {
+        self.0.offset()
+    }
+}
+
+impl Error for LexError {}
+
+/// A Rust edition, selecting the keywords and lexical rules to apply when
+/// parsing a string into tokens.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Edition {
+    Edition2015,
+    Edition2018,
+    Edition2021,
+    Edition2024,
+}
+
+impl Default for Edition {
+    fn default() -> Edition {
+        Edition::Edition2015
+    }
+}
+
+/// The category of a `LexError`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LexErrorKind {
+    /// A string or byte string literal without its closing quote.
+    UnterminatedString,
+    /// A character or byte literal without its closing quote.
+    UnterminatedChar,
+    /// A `/*` comment without its matching `*/`.
+    UnterminatedComment,
+    /// An unknown or malformed escape sequence in a literal.
+    InvalidEscape,
+    /// A number literal followed by something other than a known suffix.
+    InvalidSuffix,
+    /// A number literal with digits invalid for its base or an empty exponent.
+    InvalidNumber,
+    /// A lifetime which is not followed by an identifier, or which names a
+    /// keyword.
+    InvalidLifetime,
+    /// A raw identifier naming `_`, `self`, `Self`, `super` or `crate`.
+    InvalidRawIdent,
+    /// An identifier directly followed by `#`, `"` or `'`, which is reserved
+    /// for literal prefixes since the 2021 edition.
+    ReservedPrefix,
+    /// A closing delimiter which doesn't match the most recently opened one.
+    UnbalancedDelimiter,
+    /// An opening delimiter which is never closed.
+    UnclosedDelimiter,
+    /// A character which cannot start any token.
+    UnexpectedCharacter,
+    /// The compiler rejected the input; no further detail is available.
+    Other,
+}
+
 impl FromStr for TokenStream {
     type Err = LexError;
 
@@ -84,6 +211,18 @@ impl<T: Into<TokenStream>> FromIterator<T> for TokenStream {
     }
 }
 
+impl Extend<TokenTree> for TokenStream {
+    fn extend<I: IntoIterator<Item = TokenTree>>(&mut self, trees: I) {
+        self.0.extend(trees)
+    }
+}
+
+impl Extend<TokenStream> for TokenStream {
+    fn extend<I: IntoIterator<Item = TokenStream>>(&mut self, streams: I) {
+        self.0.extend(streams.into_iter().map(|s| s.0))
+    }
+}
+
 impl IntoIterator for TokenStream {
     type Item = TokenTree;
     type IntoIter = TokenTreeIter;
@@ -98,12 +237,89 @@ impl TokenStream {
         TokenStream(imp::TokenStream::empty())
     }
 
+    /// Like parsing with `FromStr`, but following the lexical rules of
+    /// `edition`, which affect which words are keywords and so which
+    /// lifetimes are valid, and whether an identifier may be immediately
+    /// followed by a `#` or a quote.
+    ///
+    /// With the `unstable` feature the compiler's own lexer is used, and the
+    /// edition is that of the crate being compiled regardless of `edition`.
+    pub fn parse_with_edition(src: &str, edition: Edition) -> Result<TokenStream, LexError> {
+        match imp::TokenStream::parse_with_edition(src, edition) {
+            Ok(e) => Ok(TokenStream(e)),
+            Err(e) => Err(LexError(e)),
+        }
+    }
+
+    /// Parses the output of `{:#}`, turning each `/*«*/` and its matching
+    /// `/*»*/` back into a group with `Delimiter::None` instead of skipping
+    /// them as comments. A marker without its partner is an error.
+    ///
+    /// With the `unstable` feature the compiler's lexer skips the markers, so
+    /// the groups they mark are flattened as with `FromStr`.
+    pub fn parse_alternate(src: &str) -> Result<TokenStream, LexError> {
+        match imp::TokenStream::parse_alternate(src) {
+            Ok(e) => Ok(TokenStream(e)),
+            Err(e) => Err(LexError(e)),
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Like `==`, but also requires every pair of corresponding token trees
+    /// to have equal spans.
+    pub fn eq_with_spans(&self, other: &TokenStream) -> bool {
+        let mut a = self.clone().into_iter();
+        let mut b = other.clone().into_iter();
+        loop {
+            match (a.next(), b.next()) {
+                (Some(x), Some(y)) => {
+                    if !x.eq_with_spans(&y) {
+                        return false;
+                    }
+                }
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Renders the stream as indented, multi-line source, for reading
+    /// generated code while debugging.
+    ///
+    /// Unlike `to_string` the output is meant for people rather than the
+    /// lexer: blocks are indented and statements, items and attributes go
+    /// on lines of their own. Only the tokens are used to decide the
+    /// layout, so it is a rough approximation of what rustfmt would do.
+    pub fn pretty(&self) -> String {
+        pretty::pretty(self)
+    }
+}
+
+/// Lexes `contents` as the source of the file at `path`, so that the spans
+/// of the tokens report that file as their `Span::source_file`.
+///
+/// The file isn't read; `contents` is taken to be what it holds, and the
+/// file only counts as `SourceFile::is_real` if something exists at `path`.
+/// With the `unstable` feature the compiler doesn't allow registering files,
+/// so this is the same as parsing `contents` with `FromStr`.
+pub fn parse_file<P: AsRef<Path>>(path: P, contents: &str) -> Result<TokenStream, LexError> {
+    parse_file_with_edition(path, contents, Edition::default())
+}
+
+/// Like `parse_file`, but following the lexical rules of `edition` as with
+/// `TokenStream::parse_with_edition`.
+pub fn parse_file_with_edition<P: AsRef<Path>>(path: P, contents: &str, edition: Edition)
+                                               -> Result<TokenStream, LexError> {
+    match imp::TokenStream::parse_file(path.as_ref(), contents, edition) {
+        Ok(e) => Ok(TokenStream(e)),
+        Err(e) => Err(LexError(e)),
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Span(imp::Span);
 
 impl Default for Span {
@@ -116,6 +332,109 @@ impl Span {
     pub fn call_site() -> Span {
         Span(imp::Span::call_site())
     }
+
+    /// A span which resolves names at the definition site of the macro, so
+    /// that they can't clash with names in the macro's input.
+    ///
+    /// On stable nothing is resolved, but tokens with this span still
+    /// compare unequal to call-site tokens under `eq_with_spans`.
+    pub fn def_site() -> Span {
+        Span(imp::Span::def_site())
+    }
+
+    /// A span which resolves local variables, labels and `$crate` at the
+    /// definition site of the macro, and everything else at the call site,
+    /// like `macro_rules!` does.
+    pub fn mixed_site() -> Span {
+        Span(imp::Span::mixed_site())
+    }
+
+    /// A span at the same location as `self` which resolves names like
+    /// `other` does.
+    pub fn resolved_at(&self, other: Span) -> Span {
+        Span(self.0.resolved_at(other.0))
+    }
+
+    /// A span which resolves names like `self` does, but at the location of
+    /// `other`.
+    pub fn located_at(&self, other: Span) -> Span {
+        Span(self.0.located_at(other.0))
+    }
+
+    /// A span covering both `self` and `other`, such as from the `#` of an
+    /// attribute to its closing bracket, which resolves names like `self`.
+    ///
+    /// Returns `None` if the spans come from different files.
+    pub fn join(&self, other: Span) -> Option<Span> {
+        self.0.join(other.0).map(Span)
+    }
+
+    /// Get the starting line/column in the source file for this span.
+    pub fn start(&self) -> LineColumn {
+        self.0.start()
+    }
+
+    /// Get the ending line/column in the source file for this span.
+    pub fn end(&self) -> LineColumn {
+        self.0.end()
+    }
+
+    /// The original text covered by this span, exactly as it was written,
+    /// including any comments and whitespace inside it.
+    ///
+    /// Returns `None` if the span doesn't point into source code, as with
+    /// `call_site` on stable, or if the compiler can't provide the text.
+    ///
+    /// On stable this means a copy of every string lexed is kept until the
+    /// thread exits, or until the end of the `interner::with_interner` call
+    /// it was lexed in.
+    pub fn source_text(&self) -> Option<String> {
+        self.0.source_text()
+    }
+
+    /// The file this span points into.
+    pub fn source_file(&self) -> SourceFile {
+        SourceFile(self.0.source_file())
+    }
+}
+
+/// A file of source code, as returned by `Span::source_file`.
+///
+/// On stable every string lexed into a `TokenStream` counts as a file of its
+/// own. Those parsed with `FromStr` have made-up names and aren't real;
+/// those passed to `parse_file` have the path they were given, and are real
+/// if that path named a file at the time.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SourceFile(imp::SourceFile);
+
+impl SourceFile {
+    /// The path of this file, which is only a description of where the
+    /// tokens came from unless `is_real` returns true.
+    pub fn path(&self) -> PathBuf {
+        self.0.path()
+    }
+
+    /// Whether this is an actual file on disk, rather than a string parsed
+    /// at runtime or code produced by a macro expansion.
+    ///
+    /// On stable this only means that `path` named a file when it was passed
+    /// to `parse_file`, not that the file holds the text which was lexed.
+    pub fn is_real(&self) -> bool {
+        self.0.is_real()
+    }
+}
+
+/// A line-column pair representing the start or end of a `Span`.
+///
+/// On stable the column is measured in bytes from the start of the line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LineColumn {
+    /// The 1-indexed line in the source file on which the span starts or ends
+    /// (inclusive).
+    pub line: usize,
+    /// The 0-indexed column in the source file on which the span starts, or
+    /// just past the last character of the span for its end (exclusive).
+    pub column: usize,
 }
 
 #[derive(Clone, Debug)]
@@ -126,7 +445,46 @@ pub struct TokenTree {
 
 impl From<TokenNode> for TokenTree {
     fn from(kind: TokenNode) -> TokenTree {
-        TokenTree { span: Span::default(), kind: kind }
+        let span = match kind {
+            TokenNode::Group(ref group) => group.span(),
+            _ => Span::default(),
+        };
+        TokenTree { span: span, kind: kind }
+    }
+}
+
+impl TokenTree {
+    /// Like `==`, but also compares the spans of this tree and, for a group,
+    /// of every tree nested within it.
+    pub fn eq_with_spans(&self, other: &TokenTree) -> bool {
+        if self.span != other.span {
+            return false;
+        }
+        match (&self.kind, &other.kind) {
+            (&TokenNode::Group(ref a), &TokenNode::Group(ref b)) => {
+                a.delimiter == b.delimiter &&
+                    a.span_open == b.span_open &&
+                    a.span_close == b.span_close &&
+                    a.stream.eq_with_spans(&b.stream)
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+// Spans are deliberately left out of equality and hashing, so that tokens
+// parsed from a string compare equal to the same tokens built up by hand.
+impl PartialEq for TokenTree {
+    fn eq(&self, other: &TokenTree) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for TokenTree {}
+
+impl Hash for TokenTree {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.kind.hash(state)
     }
 }
 
@@ -136,15 +494,100 @@ impl fmt::Display for TokenTree {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TokenNode {
-    Group(Delimiter, TokenStream),
+    Group(Group),
     Term(Term),
+    Lifetime(Lifetime),
     Op(char, Spacing),
     Literal(Literal),
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// A delimited token stream, along with the spans of the whole group and of
+/// its opening and closing delimiters.
+///
+/// As with `TokenTree`, the spans are ignored by `==` and hashing.
+#[derive(Clone, Debug)]
+pub struct Group {
+    delimiter: Delimiter,
+    stream: TokenStream,
+    span_open: Span,
+    span_close: Span,
+}
+
+impl Group {
+    pub fn new(delimiter: Delimiter, stream: TokenStream) -> Group {
+        Group {
+            delimiter: delimiter,
+            stream: stream,
+            span_open: Span::default(),
+            span_close: Span::default(),
+        }
+    }
+
+    pub fn delimiter(&self) -> Delimiter {
+        self.delimiter
+    }
+
+    /// The tokens between the delimiters.
+    pub fn stream(&self) -> TokenStream {
+        self.stream.clone()
+    }
+
+    /// The span of the whole group, from the opening delimiter to the
+    /// closing one.
+    ///
+    /// This is worked out from the delimiters, so it only tells where a group
+    /// came from. A `TokenTree` holding the group has a `span` of its own,
+    /// which is the one to change to move the tree somewhere else.
+    pub fn span(&self) -> Span {
+        self.span_open.join(self.span_close).unwrap_or(self.span_open)
+    }
+
+    /// The span of just the opening delimiter.
+    ///
+    /// With the `unstable` feature this is the span of the whole group.
+    pub fn span_open(&self) -> Span {
+        self.span_open
+    }
+
+    /// The span of just the closing delimiter.
+    ///
+    /// With the `unstable` feature this is the span of the whole group.
+    pub fn span_close(&self) -> Span {
+        self.span_close
+    }
+
+    /// Sets the span of the group, and of both of its delimiters, to `span`.
+    /// The spans of the tokens inside the group are left as they are.
+    pub fn set_span(&mut self, span: Span) {
+        self.span_open = span;
+        self.span_close = span;
+    }
+}
+
+impl PartialEq for Group {
+    fn eq(&self, other: &Group) -> bool {
+        self.delimiter == other.delimiter && self.stream == other.stream
+    }
+}
+
+impl Eq for Group {}
+
+impl Hash for Group {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.delimiter.hash(state);
+        self.stream.hash(state);
+    }
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        TokenTree::from(TokenNode::Group(self.clone())).fmt(f)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Delimiter {
     Parenthesis,
     Brace,
@@ -152,28 +595,201 @@ pub enum Delimiter {
     None,
 }
 
-#[derive(Copy, Clone)]
+/// An identifier or keyword.
+///
+/// Terms are interned, so cloning one is cheap and comparing two of them is
+/// usually just a comparison of their symbols.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Term(imp::Term);
 
 impl Term {
+    /// Interns `string` as a term.
+    ///
+    /// A string starting with `r#`, such as `r#match`, is a raw identifier
+    /// and gives the same term as passing the rest of it to `intern_raw`.
+    /// The text of a lifetime, such as `'a`, is also accepted for terms which
+    /// stand in for one; see `is_lifetime`.
+    ///
+    /// In debug builds this panics unless `string` is an identifier or a
+    /// lifetime, since anything else can't be turned back into tokens by the
+    /// compiler. Use `try_new` to check strings which may not be valid.
     pub fn intern(string: &str) -> Term {
+        if string.starts_with("r#") {
+            return Term::intern_raw(&string[2..]);
+        }
+        debug_assert!(ident::is_ident_chars(string) || ident::is_lifetime(string),
+                      "`{}` is not a valid identifier", string);
+        Term::unchecked(string)
+    }
+
+    /// Interns `string` as a term if it is an identifier, following the same
+    /// rules as the lexer: a letter or `_` followed by any number of letters,
+    /// digits and `_`s, where letters are those with the Unicode
+    /// `XID_Start` and `XID_Continue` properties. Keywords are allowed, and
+    /// so are raw identifiers such as `r#match`, but unlike with `intern`
+    /// lifetimes aren't; those belong in a `Lifetime`.
+    pub fn try_new(string: &str) -> Result<Term, TermError> {
+        ident::check_ident(string)?;
+        Ok(Term::intern(string))
+    }
+
+    // For the lexer and lifetimes, which have already checked their text.
+    fn unchecked(string: &str) -> Term {
         Term(string.into())
     }
 
+    /// Interns a raw identifier such as `r#match`, given the name without
+    /// its `r#` prefix, for using keywords as ordinary identifiers.
+    ///
+    /// Like `intern`, this checks `string` in debug builds, which must also
+    /// not be one of the words such as `self` which can't be raw.
+    pub fn intern_raw(string: &str) -> Term {
+        debug_assert!(ident::is_ident_chars(string) && ident::can_be_raw(string),
+                      "`{}` is not a valid raw identifier", string);
+        Term(imp::Term::raw(string))
+    }
+
+    /// The name of the term, without the `r#` prefix of a raw identifier.
     pub fn as_str(&self) -> &str {
         &self.0
     }
+
+    pub fn is_raw(&self) -> bool {
+        self.0.is_raw()
+    }
+
+    /// Creates a term usable as an identifier from any string, such as a
+    /// name taken from user input or from a file.
+    ///
+    /// Characters which can't appear in an identifier are replaced with `_`,
+    /// keywords become raw identifiers, and `self`, `Self`, `super`, `crate`
+    /// and `_`, which can't be raw, get a trailing `_` instead.
+    pub fn intern_ident(string: &str) -> Term {
+        match ident::to_ident(string) {
+            (ident, true) => Term::intern_raw(&ident),
+            (ident, false) => Term::intern(&ident),
+        }
+    }
+
+    /// Whether this term holds the text of a lifetime, such as `'a`, as
+    /// passed to `intern`.
+    ///
+    /// Lifetimes which are lexed or quoted are `TokenNode::Lifetime` rather
+    /// than terms, but code written before that was added keeps them as
+    /// terms, and can tell them apart from identifiers with this.
+    pub fn is_lifetime(&self) -> bool {
+        self.as_str().starts_with('\'')
+    }
+
+    /// Whether this is a strict or reserved keyword in any edition. Raw
+    /// identifiers and lifetimes are never keywords.
+    pub fn is_keyword(&self) -> bool {
+        !self.is_raw() && !self.is_lifetime() &&
+            ident::is_keyword(self.as_str(), Edition::Edition2024)
+    }
+
+    /// How this term is reserved by the language in `edition`, if at all.
+    pub fn keyword_class(&self, edition: Edition) -> Option<KeywordClass> {
+        if self.is_raw() || self.is_lifetime() {
+            None
+        } else {
+            ident::keyword_class(self.as_str(), edition)
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_raw() {
+            f.write_str("r#")?;
+        }
+        f.write_str(self.as_str())
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// A lifetime or loop label such as `'a`.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Lifetime(Term);
+
+impl Lifetime {
+    /// Creates a lifetime from its text, including the leading `'`.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `s` is a `'` followed by an identifier which isn't a
+    /// keyword, other than `'static`.
+    pub fn new(s: &str) -> Lifetime {
+        if !ident::is_lifetime(s) {
+            panic!("`{}` is not a valid lifetime", s);
+        }
+        Lifetime(Term::unchecked(s))
+    }
+
+    /// The text of the lifetime, including the leading `'`.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// The name of the lifetime, without the leading `'`.
+    pub fn name(&self) -> &str {
+        &self.as_str()[1..]
+    }
+}
+
+impl fmt::Display for Lifetime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Lifetime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Lifetime").field(&self.as_str()).finish()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Spacing {
     Alone,
     Joint,
 }
 
-#[derive(Clone)]
+/// A literal such as `"foo"`, `'a'` or `1u8`.
+///
+/// Literals compare equal when their token text is identical, so `1u8` and
+/// `0x1u8` are considered different.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Literal(imp::Literal);
 
+/// The different sorts of token which the lexer treats as a `Literal`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum LiteralKind {
+    /// `"foo"`
+    Str,
+    /// `r"foo"`, `r#"foo"#`, ...
+    RawStr { pounds: usize },
+    /// `b"foo"`
+    ByteStr,
+    /// `br"foo"`, `br#"foo"#`, ...
+    RawByteStr { pounds: usize },
+    /// `c"foo"`, since the 2021 edition
+    CStr,
+    /// `cr"foo"`, `cr#"foo"#`, ..., since the 2021 edition
+    RawCStr { pounds: usize },
+    /// `'a'`
+    Char,
+    /// `b'a'`
+    Byte,
+    /// `1`, `0xff_u8`, ...
+    Int,
+    /// `1.0`, `1e3`, `1f32`, ...
+    Float,
+    /// `true` or `false`
+    Bool,
+    /// `/// foo`, `/** foo */`, ...
+    DocComment,
+}
+
 macro_rules! int_literals {
     ($($kind:ident,)*) => ($(
         pub fn $kind(n: $kind) -> Literal {
@@ -234,6 +850,51 @@ impl Literal {
     pub fn raw_byte_string(s: &str, pounds: usize) -> Literal {
         Literal(imp::Literal::raw_byte_string(s, pounds))
     }
+
+    pub fn kind(&self) -> LiteralKind {
+        self.0.kind()
+    }
+
+    /// The suffix of the literal, such as `u8` in `1u8`.
+    pub fn suffix(&self) -> Option<String> {
+        lit::literal_suffix(self.kind(), &self.to_string())
+    }
+
+    /// The unescaped contents of a string or raw string literal.
+    pub fn string_value(&self) -> Result<String, LitError> {
+        lit::string(self.kind(), &self.to_string())
+    }
+
+    /// The unescaped contents of a byte string or raw byte string literal.
+    pub fn byte_string_value(&self) -> Result<Vec<u8>, LitError> {
+        lit::byte_string(self.kind(), &self.to_string())
+    }
+
+    pub fn char_value(&self) -> Result<char, LitError> {
+        lit::character(self.kind(), &self.to_string())
+    }
+
+    pub fn byte_value(&self) -> Result<u8, LitError> {
+        lit::byte(self.kind(), &self.to_string())
+    }
+
+    /// The value of an integer literal in any radix, along with its suffix
+    /// if it has one.
+    pub fn u128_value(&self) -> Result<(u128, Option<String>), LitError> {
+        lit::unsigned(self.kind(), &self.to_string())
+    }
+
+    /// Like `u128_value`, but also accepting the negative literals produced
+    /// by constructors such as `Literal::i32(-1)`.
+    pub fn i128_value(&self) -> Result<(i128, Option<String>), LitError> {
+        lit::signed(self.kind(), &self.to_string())
+    }
+
+    /// The value of a floating point literal, along with its suffix if it
+    /// has one.
+    pub fn f64_value(&self) -> Result<(f64, Option<String>), LitError> {
+        lit::float(self.kind(), &self.to_string())
+    }
 }
 
 pub struct TokenTreeIter(imp::TokenTreeIter);
@@ -247,8 +908,10 @@ impl Iterator for TokenTreeIter {
 }
 
 forward_fmt!(Debug for LexError);
+forward_fmt!(Display for LexError);
 forward_fmt!(Debug for Literal);
 forward_fmt!(Debug for Span);
+forward_fmt!(Debug for SourceFile);
 forward_fmt!(Debug for Term);
 forward_fmt!(Debug for TokenTreeIter);
 forward_fmt!(Debug for TokenStream);
@@ -0,0 +1,443 @@
+//! Decoding the value of a literal from its source text.
+//!
+//! Both backends can hand out the text of a literal, so all of the decoding
+//! happens here on plain strings rather than once per backend.
+
+use std::char;
+use std::error::Error;
+use std::fmt;
+
+use LiteralKind;
+
+/// The ways in which decoding the value of a `Literal` can fail.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LitErrorKind {
+    /// The literal is of a different kind than the one requested, such as
+    /// asking for the integer value of a string literal.
+    WrongKind,
+    /// A backslash escape that isn't valid in this kind of literal.
+    InvalidEscape,
+    /// A digit which is out of range for the integer's radix.
+    InvalidDigit,
+    /// The value doesn't fit in the requested type.
+    Overflow,
+    /// The literal text is otherwise not well formed.
+    Malformed,
+}
+
+/// Error returned by the value accessors on `Literal`.
+#[derive(Copy, Clone, Debug)]
+pub struct LitError {
+    kind: LitErrorKind,
+}
+
+impl LitError {
+    fn new(kind: LitErrorKind) -> LitError {
+        LitError { kind: kind }
+    }
+
+    pub fn kind(&self) -> LitErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for LitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self.kind {
+            LitErrorKind::WrongKind => "literal is not of the requested kind",
+            LitErrorKind::InvalidEscape => "invalid escape in literal",
+            LitErrorKind::InvalidDigit => "invalid digit for the base of integer literal",
+            LitErrorKind::Overflow => "literal value out of range",
+            LitErrorKind::Malformed => "malformed literal",
+        })
+    }
+}
+
+impl Error for LitError {}
+
+type Result<T> = ::std::result::Result<T, LitError>;
+
+fn err<T>(kind: LitErrorKind) -> Result<T> {
+    Err(LitError::new(kind))
+}
+
+fn suffix(s: &str) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s.to_string())
+    }
+}
+
+/// Works out the kind of a literal from its text, for literals which didn't
+/// come out of our own lexer.
+pub fn classify(repr: &str) -> LiteralKind {
+    let pounds = |s: &str| s.bytes().take_while(|&b| b == b'#').count();
+    if repr.starts_with("//") || repr.starts_with("/*") {
+        LiteralKind::DocComment
+    } else if repr == "true" || repr == "false" {
+        LiteralKind::Bool
+    } else if repr.starts_with("b\"") {
+        LiteralKind::ByteStr
+    } else if repr.starts_with("br") {
+        LiteralKind::RawByteStr { pounds: pounds(&repr[2..]) }
+    } else if repr.starts_with("c\"") {
+        LiteralKind::CStr
+    } else if repr.starts_with("cr") {
+        LiteralKind::RawCStr { pounds: pounds(&repr[2..]) }
+    } else if repr.starts_with("b'") {
+        LiteralKind::Byte
+    } else if repr.starts_with('r') {
+        LiteralKind::RawStr { pounds: pounds(&repr[1..]) }
+    } else if repr.starts_with('"') {
+        LiteralKind::Str
+    } else if repr.starts_with('\'') {
+        LiteralKind::Char
+    } else {
+        let s = if repr.starts_with('-') { &repr[1..] } else { repr };
+        let digits = s.find(|c: char| !c.is_digit(10) && c != '_').unwrap_or(s.len());
+        let rest = &s[digits..];
+        if s.starts_with("0x") || s.starts_with("0o") || s.starts_with("0b") {
+            LiteralKind::Int
+        } else if rest.starts_with('.') || rest.starts_with('e') || rest.starts_with('E') ||
+                  rest == "f32" || rest == "f64" {
+            LiteralKind::Float
+        } else {
+            LiteralKind::Int
+        }
+    }
+}
+
+/// The suffix of a literal, if it has one.
+pub fn literal_suffix(kind: LiteralKind, repr: &str) -> Option<String> {
+    let rest = match kind {
+        LiteralKind::Str => cooked(&repr[1..], false).map(|(_, rest)| rest),
+        LiteralKind::ByteStr => cooked(&repr[2..], true).map(|(_, rest)| rest),
+        LiteralKind::RawStr { .. } => raw(&repr[1..]).map(|(_, rest)| rest),
+        LiteralKind::RawByteStr { .. } => raw(&repr[2..]).map(|(_, rest)| rest),
+        LiteralKind::CStr => cooked(&repr[2..], false).map(|(_, rest)| rest),
+        LiteralKind::RawCStr { .. } => raw(&repr[2..]).map(|(_, rest)| rest),
+        LiteralKind::Char => single(&repr[1..], false).map(|(_, rest)| rest),
+        LiteralKind::Byte => single(&repr[2..], true).map(|(_, rest)| rest),
+        LiteralKind::Int => return int(repr).ok().and_then(|(_, _, suffix)| suffix),
+        LiteralKind::Float => return parse_float(repr).ok().and_then(|(_, suffix)| suffix),
+        LiteralKind::Bool | LiteralKind::DocComment => return None,
+    };
+    rest.ok().and_then(suffix)
+}
+
+/// The value of a cooked or raw string literal.
+pub fn string(kind: LiteralKind, repr: &str) -> Result<String> {
+    match kind {
+        LiteralKind::Str => {
+            let (chars, _) = cooked(&repr[1..], false)?;
+            Ok(chars.into_iter().collect())
+        }
+        LiteralKind::RawStr { .. } => {
+            let (s, _) = raw(&repr[1..])?;
+            Ok(s.replace("\r\n", "\n"))
+        }
+        _ => err(LitErrorKind::WrongKind),
+    }
+}
+
+/// The value of a cooked or raw byte string literal.
+pub fn byte_string(kind: LiteralKind, repr: &str) -> Result<Vec<u8>> {
+    match kind {
+        LiteralKind::ByteStr => {
+            let (chars, _) = cooked(&repr[2..], true)?;
+            Ok(chars.into_iter().map(|c| c as u8).collect())
+        }
+        LiteralKind::RawByteStr { .. } => {
+            let (s, _) = raw(&repr[2..])?;
+            if !s.is_ascii() {
+                return err(LitErrorKind::Malformed);
+            }
+            Ok(s.replace("\r\n", "\n").into_bytes())
+        }
+        _ => err(LitErrorKind::WrongKind),
+    }
+}
+
+/// The value of a character literal.
+pub fn character(kind: LiteralKind, repr: &str) -> Result<char> {
+    match kind {
+        LiteralKind::Char => single(&repr[1..], false).map(|(ch, _)| ch),
+        _ => err(LitErrorKind::WrongKind),
+    }
+}
+
+/// The value of a byte literal.
+pub fn byte(kind: LiteralKind, repr: &str) -> Result<u8> {
+    match kind {
+        LiteralKind::Byte => single(&repr[2..], true).map(|(ch, _)| ch as u8),
+        _ => err(LitErrorKind::WrongKind),
+    }
+}
+
+/// The value of a non-negative integer literal and its suffix.
+pub fn unsigned(kind: LiteralKind, repr: &str) -> Result<(u128, Option<String>)> {
+    if kind != LiteralKind::Int {
+        return err(LitErrorKind::WrongKind);
+    }
+    match int(repr)? {
+        (true, n, _) if n != 0 => err(LitErrorKind::Overflow),
+        (_, n, suffix) => Ok((n, suffix)),
+    }
+}
+
+/// The value of a possibly negative integer literal and its suffix.
+pub fn signed(kind: LiteralKind, repr: &str) -> Result<(i128, Option<String>)> {
+    if kind != LiteralKind::Int {
+        return err(LitErrorKind::WrongKind);
+    }
+    let (neg, n, suffix) = int(repr)?;
+    let max = i128::max_value() as u128;
+    if neg && n <= max + 1 {
+        Ok(((n as i128).wrapping_neg(), suffix))
+    } else if !neg && n <= max {
+        Ok((n as i128, suffix))
+    } else {
+        err(LitErrorKind::Overflow)
+    }
+}
+
+// The magnitude of an integer literal in any radix, whether it was negative
+// and its suffix.
+//
+// Literals are never negative in source code, but the stable constructors
+// such as `Literal::i32(-1)` produce a leading `-`.
+fn int(repr: &str) -> Result<(bool, u128, Option<String>)> {
+    let neg = repr.starts_with('-');
+    let s = if neg { &repr[1..] } else { repr };
+    let (base, s) = if s.starts_with("0x") {
+        (16, &s[2..])
+    } else if s.starts_with("0o") {
+        (8, &s[2..])
+    } else if s.starts_with("0b") {
+        (2, &s[2..])
+    } else {
+        (10, s)
+    };
+
+    let mut value: u128 = 0;
+    let mut digits = 0;
+    let mut end = s.len();
+    for (i, ch) in s.char_indices() {
+        let digit = match ch.to_digit(if base == 16 { 16 } else { 10 }) {
+            Some(digit) => digit,
+            None if ch == '_' => continue,
+            None => {
+                end = i;
+                break;
+            }
+        };
+        if digit >= base {
+            return err(LitErrorKind::InvalidDigit);
+        }
+        value = match value.checked_mul(base as u128)
+            .and_then(|v| v.checked_add(digit as u128)) {
+            Some(value) => value,
+            None => return err(LitErrorKind::Overflow),
+        };
+        digits += 1;
+    }
+
+    if digits == 0 {
+        return err(LitErrorKind::Malformed);
+    }
+    if let Some((max, neg_max)) = suffix_range(&s[end..]) {
+        if value > if neg { neg_max } else { max } {
+            return err(LitErrorKind::Overflow);
+        }
+    }
+    Ok((neg, value, suffix(&s[end..])))
+}
+
+// The largest magnitude an integer literal with `suffix` can have when
+// positive and when negative, or `None` if the suffix doesn't name an integer
+// type.
+fn suffix_range(suffix: &str) -> Option<(u128, u128)> {
+    macro_rules! unsigned {
+        ($t:ident) => (($t::max_value() as u128, 0))
+    }
+    macro_rules! signed {
+        ($t:ident) => (($t::max_value() as u128, $t::max_value() as u128 + 1))
+    }
+    Some(match suffix {
+        "u8" => unsigned!(u8),
+        "u16" => unsigned!(u16),
+        "u32" => unsigned!(u32),
+        "u64" => unsigned!(u64),
+        "u128" => unsigned!(u128),
+        "usize" => unsigned!(usize),
+        "i8" => signed!(i8),
+        "i16" => signed!(i16),
+        "i32" => signed!(i32),
+        "i64" => signed!(i64),
+        "i128" => signed!(i128),
+        "isize" => signed!(isize),
+        _ => return None,
+    })
+}
+
+/// The value of a floating point literal and its suffix.
+pub fn float(kind: LiteralKind, repr: &str) -> Result<(f64, Option<String>)> {
+    if kind != LiteralKind::Float {
+        return err(LitErrorKind::WrongKind);
+    }
+    parse_float(repr)
+}
+
+fn parse_float(repr: &str) -> Result<(f64, Option<String>)> {
+    let neg = repr.starts_with('-');
+    let s = if neg { &repr[1..] } else { repr };
+    let bytes = s.as_bytes();
+    let digits = |mut i: usize| {
+        while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'_') {
+            i += 1;
+        }
+        i
+    };
+
+    let mut end = digits(0);
+    if end == 0 || s.starts_with("0x") || s.starts_with("0o") || s.starts_with("0b") {
+        return err(LitErrorKind::Malformed);
+    }
+    let mut is_float = false;
+    if end < bytes.len() && bytes[end] == b'.' {
+        end = digits(end + 1);
+        is_float = true;
+    }
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut i = end + 1;
+        if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+            i += 1;
+        }
+        let exp = digits(i);
+        if !s[i..exp].bytes().any(|b| b.is_ascii_digit()) {
+            return err(LitErrorKind::Malformed);
+        }
+        end = exp;
+        is_float = true;
+    }
+
+    let rest = &s[end..];
+    if !is_float && rest != "f32" && rest != "f64" {
+        return err(LitErrorKind::Malformed);
+    }
+    let number = repr[..end + neg as usize].replace('_', "");
+    match number.parse::<f64>() {
+        Ok(value) if value.is_infinite() => err(LitErrorKind::Overflow),
+        Ok(value) => Ok((value, suffix(rest))),
+        Err(_) => err(LitErrorKind::Malformed),
+    }
+}
+
+// The contents of a raw string, given the text after its `r`, and the input
+// after its closing quote.
+fn raw(s: &str) -> Result<(&str, &str)> {
+    let pounds = s.bytes().take_while(|&b| b == b'#').count();
+    let s = &s[pounds..];
+    if !s.starts_with('"') {
+        return err(LitErrorKind::Malformed);
+    }
+    let close = format!("\"{}", &"#".repeat(pounds));
+    match s[1..].find(&close) {
+        Some(end) => Ok((&s[1..end + 1], &s[end + 1 + close.len()..])),
+        None => err(LitErrorKind::Malformed),
+    }
+}
+
+// Decodes the body of a cooked string up to the closing quote, returning the
+// input after it. `byte` selects the rules for byte strings, in which case
+// every returned char is at most `\xFF`.
+fn cooked(mut s: &str, byte: bool) -> Result<(Vec<char>, &str)> {
+    let mut chars = Vec::new();
+    loop {
+        let ch = match s.chars().next() {
+            Some(ch) => ch,
+            None => return err(LitErrorKind::Malformed),
+        };
+        s = &s[ch.len_utf8()..];
+        match ch {
+            '\\' if s.starts_with('\n') || s.starts_with("\r\n") => {
+                // A line continuation skips the newline and any leading
+                // whitespace on the next line.
+                s = s.find(|c: char| !c.is_whitespace()).map_or("", |i| &s[i..]);
+            }
+            '\\' => {
+                let (ch, rest) = backslash(s, byte)?;
+                chars.push(ch);
+                s = rest;
+            }
+            '\r' if s.starts_with('\n') => {}
+            '"' => return Ok((chars, s)),
+            ch if byte && !ch.is_ascii() => return err(LitErrorKind::Malformed),
+            ch => chars.push(ch),
+        }
+    }
+}
+
+// Decodes the body of a char or byte literal, which must hold exactly one
+// possibly escaped character, returning the input after the closing quote.
+fn single(s: &str, byte: bool) -> Result<(char, &str)> {
+    let (ch, rest) = if s.starts_with('\\') {
+        backslash(&s[1..], byte)?
+    } else {
+        match s.chars().next() {
+            Some('\'') | None => return err(LitErrorKind::Malformed),
+            Some(ch) if byte && !ch.is_ascii() => return err(LitErrorKind::Malformed),
+            Some(ch) => (ch, &s[ch.len_utf8()..]),
+        }
+    };
+    if rest.starts_with('\'') {
+        Ok((ch, &rest[1..]))
+    } else {
+        err(LitErrorKind::Malformed)
+    }
+}
+
+// Decodes the escape following a backslash, returning the character and the
+// input after the escape.
+fn backslash(s: &str, byte: bool) -> Result<(char, &str)> {
+    let ch = match s.chars().next() {
+        Some(ch) => ch,
+        None => return err(LitErrorKind::Malformed),
+    };
+    let rest = &s[ch.len_utf8()..];
+    let simple = match ch {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        '\\' => '\\',
+        '0' => '\0',
+        '\'' => '\'',
+        '"' => '"',
+        'x' => {
+            let hex = rest.get(..2)
+                .filter(|h| h.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            return match hex {
+                Some(b) if byte || b <= 0x7f => Ok((b as char, &rest[2..])),
+                _ => err(LitErrorKind::InvalidEscape),
+            };
+        }
+        'u' if !byte && rest.starts_with('{') => {
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => return err(LitErrorKind::InvalidEscape),
+            };
+            let hex = rest[1..end].replace('_', "");
+            if hex.is_empty() || hex.len() > 6 {
+                return err(LitErrorKind::InvalidEscape);
+            }
+            return match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                Some(ch) => Ok((ch, &rest[end + 1..])),
+                None => err(LitErrorKind::InvalidEscape),
+            };
+        }
+        _ => return err(LitErrorKind::InvalidEscape),
+    };
+    Ok((simple, rest))
+}
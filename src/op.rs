@@ -0,0 +1,91 @@
+//! Gluing runs of single-character `Op` tokens into Rust's compound
+//! operators, and splitting operators back apart.
+//!
+//! A token stream only ever holds one operator character per token, with
+//! `Spacing::Joint` marking a character that is immediately followed by
+//! another. So `<<=` arrives as `<` and `<` (both joint) followed by `=`, and
+//! it's up to the consumer to put them back together.
+
+use buffer::Cursor;
+use {Spacing, Span, TokenNode, TokenStream, TokenTree};
+
+// Every multi-character operator in the language, longest first so that the
+// first match for a run of characters is also the longest.
+static OPERATORS: &'static [&'static str] = &[
+    "<<=", ">>=", "...", "..=",
+    "::", "->", "=>", "<-", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "^=", "&=", "|=", "<<", ">>", "..",
+];
+
+// The characters which can appear in an `Op` token.
+static OP_CHARS: &'static str = "~!@#$%^&*-=+|;:,<.>/?";
+
+/// Whether `s` is a single operator, either one of the compound operators
+/// or a lone operator character.
+pub fn is_operator(s: &str) -> bool {
+    lookup(s).is_some()
+}
+
+fn lookup(s: &str) -> Option<&'static str> {
+    if let Some(op) = OPERATORS.iter().find(|op| **op == s) {
+        return Some(op);
+    }
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => OP_CHARS.find(ch).map(|i| &OP_CHARS[i..i + 1]),
+        _ => None,
+    }
+}
+
+/// Glues the operator at the start of `cursor`, returning it along with a
+/// span covering all of its characters and a cursor past it.
+///
+/// Only characters joined by `Spacing::Joint` are combined, and the longest
+/// operator wins, so `<<=` is a single operator while `< <=` is two and
+/// `&&&` is `&&` followed by `&`. Returns `None` if the next token isn't an
+/// `Op`.
+///
+/// The combined span falls back to the span of the first character if the
+/// spans can't be joined.
+pub fn glue(cursor: Cursor) -> Option<(&'static str, Span, Cursor)> {
+    let mut run = Vec::new();
+    let mut rest = cursor;
+    while let Some((ch, spacing, span, next)) = rest.op() {
+        run.push((ch, span, next));
+        rest = next;
+        if spacing == Spacing::Alone || run.len() == 3 {
+            break;
+        }
+    }
+
+    while !run.is_empty() {
+        let text = run.iter().map(|&(ch, _, _)| ch).collect::<String>();
+        if let Some(op) = lookup(&text) {
+            let (_, first, _) = run[0];
+            let (_, last, after) = run[run.len() - 1];
+            let span = first.join(last).unwrap_or(first);
+            return Some((op, span, after));
+        }
+        run.pop();
+    }
+    None
+}
+
+/// The `Op` tokens making up the operator `op`, all carrying `span`.
+///
+/// Every character but the last is `Spacing::Joint`, and the last is
+/// `Spacing::Alone`. Returns `None` if `op` isn't an operator.
+pub fn split(op: &str, span: Span) -> Option<TokenStream> {
+    if !is_operator(op) {
+        return None;
+    }
+    let len = op.chars().count();
+    let tokens = op.chars().enumerate().map(|(i, ch)| {
+        let spacing = if i + 1 == len { Spacing::Alone } else { Spacing::Joint };
+        TokenTree {
+            span: span,
+            kind: TokenNode::Op(ch, spacing),
+        }
+    });
+    Some(tokens.collect())
+}
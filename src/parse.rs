@@ -0,0 +1,552 @@
+//! Parsing Rust syntax out of a `TokenStream`.
+//!
+//! Types implement `Parse` to describe how they are read from a
+//! `ParseStream`, which offers one-token lookahead through `peek`, arbitrary
+//! backtracking through `fork`, and span-tagged errors throughout.
+//!
+//! ```ignore
+//! struct Field {
+//!     name: Term,
+//!     ty: TokenStream,
+//! }
+//!
+//! impl Parse for Field {
+//!     fn parse(input: ParseStream) -> Result<Field> {
+//!         let name = input.parse()?;
+//!         input.expect(":")?;
+//!         let mut ty = Vec::new();
+//!         while !input.is_empty() && !input.peek(',') {
+//!             ty.push(input.parse::<TokenTree>()?);
+//!         }
+//!         Ok(Field { name: name, ty: ty.into_iter().collect() })
+//!     }
+//! }
+//!
+//! let (_, fields) = input.braced(|content| {
+//!     Punctuated::parse_terminated(content, ",", Field::parse)
+//! })?;
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::error;
+use std::fmt;
+use std::slice;
+use std::vec;
+
+use buffer::{Cursor, TokenBuffer};
+use op;
+use {Delimiter, Diagnostic, Level, LexError, Lifetime, Literal, LiteralKind, Spacing, Span, Term,
+     TokenStream, TokenTree};
+
+/// A parse error: a message along with the span it refers to.
+#[derive(Clone, Debug)]
+pub struct Error {
+    span: Span,
+    message: String,
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new<T: fmt::Display>(span: Span, message: T) -> Error {
+        Error {
+            span: span,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Renders this error as a `compile_error!` invocation; see
+    /// `Diagnostic::to_compile_error`.
+    pub fn to_compile_error(&self) -> TokenStream {
+        Diagnostic::from(self.clone()).to_compile_error()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for Diagnostic {
+    fn from(err: Error) -> Diagnostic {
+        Diagnostic::spanned(err.span, Level::Error, err.message)
+    }
+}
+
+impl From<LexError> for Error {
+    fn from(err: LexError) -> Error {
+        Error::new(err.span(), err)
+    }
+}
+
+/// Types which can be parsed out of a `ParseStream`.
+pub trait Parse: Sized {
+    fn parse(input: ParseStream) -> Result<Self>;
+}
+
+/// The input to a `Parse` implementation.
+pub type ParseStream<'c, 'a> = &'c ParseBuffer<'a>;
+
+/// A cursor into a `TokenBuffer` which is advanced as values are parsed out
+/// of it.
+pub struct ParseBuffer<'a> {
+    cell: Cell<Cursor<'a>>,
+}
+
+/// Something to look for in a `ParseStream`.
+///
+/// This is implemented for `char`, matching a single op; for `&str`, matching
+/// an identifier or keyword, or a whole run of joint ops such as `::` or `->`;
+/// and for `Delimiter`, matching a group.
+///
+/// A string never matches the start of a longer operator, so `"<"` doesn't
+/// match the `<` of `<=` while `'<'` does, though `"::"` still matches before
+/// the `<` of `::<`. The empty string matches nothing.
+pub trait Token {
+    #[doc(hidden)]
+    fn step<'a>(&self, cursor: Cursor<'a>) -> Option<Cursor<'a>>;
+
+    #[doc(hidden)]
+    fn display(&self) -> String;
+}
+
+impl Token for char {
+    fn step<'a>(&self, cursor: Cursor<'a>) -> Option<Cursor<'a>> {
+        match cursor.op() {
+            Some((ch, _, _, rest)) if ch == *self => Some(rest),
+            _ => None,
+        }
+    }
+
+    fn display(&self) -> String {
+        format!("`{}`", self)
+    }
+}
+
+impl<'b> Token for &'b str {
+    fn step<'a>(&self, cursor: Cursor<'a>) -> Option<Cursor<'a>> {
+        if self.is_empty() {
+            return None;
+        }
+        if let Some((term, _, rest)) = cursor.term() {
+            // `r#struct` is an identifier, never the keyword `struct`.
+            let matches = !term.is_raw() && term.as_str() == *self;
+            return if matches { Some(rest) } else { None };
+        }
+        if let Some((lit, _, rest)) = cursor.literal() {
+            // `true` and `false` are lexed as literals, not terms.
+            let matches = lit.kind() == LiteralKind::Bool && lit.to_string() == *self;
+            return if matches { Some(rest) } else { None };
+        }
+        let mut cursor = cursor;
+        let mut chars = self.chars().peekable();
+        while let Some(expected) = chars.next() {
+            match cursor.op() {
+                Some((ch, spacing, _, rest)) if ch == expected => {
+                    let last = chars.peek().is_none();
+                    if spacing == Spacing::Alone && !last {
+                        return None;
+                    }
+                    if spacing == Spacing::Joint && last && continues_operator(self, rest) {
+                        return None;
+                    }
+                    cursor = rest;
+                }
+                _ => return None,
+            }
+        }
+        Some(cursor)
+    }
+
+    fn display(&self) -> String {
+        format!("`{}`", self)
+    }
+}
+
+// Whether the op at `cursor` would glue onto the end of `op` to make a longer
+// operator, as `=` does to `<`.
+fn continues_operator(op: &str, cursor: Cursor) -> bool {
+    match cursor.op() {
+        Some((next, _, _, _)) => {
+            let mut longer = op.to_string();
+            longer.push(next);
+            op::is_operator(&longer)
+        }
+        None => false,
+    }
+}
+
+impl Token for Delimiter {
+    fn step<'a>(&self, cursor: Cursor<'a>) -> Option<Cursor<'a>> {
+        cursor.group(*self).map(|(_, _, rest)| rest)
+    }
+
+    fn display(&self) -> String {
+        match *self {
+            Delimiter::Parenthesis => "parentheses",
+            Delimiter::Brace => "curly braces",
+            Delimiter::Bracket => "square brackets",
+            Delimiter::None => "invisible group",
+        }.to_string()
+    }
+}
+
+impl<'a> ParseBuffer<'a> {
+    fn new(cursor: Cursor<'a>) -> ParseBuffer<'a> {
+        ParseBuffer { cell: Cell::new(cursor) }
+    }
+
+    pub fn parse<T: Parse>(&self) -> Result<T> {
+        T::parse(self)
+    }
+
+    /// Calls a parser function which isn't a `Parse` impl, for example one
+    /// which takes extra arguments through a closure.
+    pub fn call<T, F: FnOnce(ParseStream) -> Result<T>>(&self, parser: F) -> Result<T> {
+        parser(self)
+    }
+
+    /// Whether every token at this level has been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.cursor().eof()
+    }
+
+    /// Whether the next token is `token`, without consuming it.
+    pub fn peek<T: Token>(&self, token: T) -> bool {
+        token.step(self.cursor()).is_some()
+    }
+
+    /// Whether the token tree `n` trees ahead is `token`.
+    pub fn peek_nth<T: Token>(&self, n: usize, token: T) -> bool {
+        match self.cursor().advance(n) {
+            Some(cursor) => token.step(cursor).is_some(),
+            None => false,
+        }
+    }
+
+    /// Consumes `token`, returning the span of its first token, or fails
+    /// without consuming anything.
+    pub fn expect<T: Token>(&self, token: T) -> Result<Span> {
+        let cursor = self.cursor();
+        match token.step(cursor) {
+            Some(rest) => {
+                self.cell.set(rest);
+                Ok(cursor.span())
+            }
+            None => Err(self.unexpected(&[token.display()])),
+        }
+    }
+
+    /// Starts a lookahead which remembers what was peeked for, so that its
+    /// error can list all of the alternatives.
+    pub fn lookahead1(&self) -> Lookahead1<'a> {
+        Lookahead1 {
+            cursor: self.cursor(),
+            comparisons: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// A copy of this stream which can be parsed from speculatively without
+    /// affecting `self`. Commit to what it consumed with `advance_to`.
+    pub fn fork(&self) -> ParseBuffer<'a> {
+        ParseBuffer::new(self.cursor())
+    }
+
+    /// Moves `self` to wherever `fork` has got to.
+    pub fn advance_to(&self, fork: &ParseBuffer<'a>) {
+        self.cell.set(fork.cursor());
+    }
+
+    /// Runs a low-level parser on the underlying cursor, consuming what it
+    /// consumed if it succeeds.
+    pub fn step<R, F>(&self, f: F) -> Result<R>
+        where F: FnOnce(Cursor<'a>) -> Result<(R, Cursor<'a>)>
+    {
+        let (node, rest) = f(self.cursor())?;
+        self.cell.set(rest);
+        Ok(node)
+    }
+
+    pub fn cursor(&self) -> Cursor<'a> {
+        self.cell.get()
+    }
+
+    /// The span of the next token, or of the enclosing group at the end of
+    /// its contents.
+    pub fn span(&self) -> Span {
+        self.cursor().span()
+    }
+
+    /// An error pointing at the next token.
+    pub fn error<T: fmt::Display>(&self, message: T) -> Error {
+        Error::new(self.span(), message)
+    }
+
+    /// Parses a group delimited by `delim`, running `parser` over its
+    /// contents and failing if any tokens inside are left over. Returns the
+    /// span of the group along with the parsed value.
+    pub fn group<T, F>(&self, delim: Delimiter, parser: F) -> Result<(Span, T)>
+        where F: FnOnce(ParseStream) -> Result<T>
+    {
+        match self.cursor().group(delim) {
+            Some((inside, span, rest)) => {
+                let content = ParseBuffer::new(inside);
+                let node = parser(&content)?;
+                if !content.is_empty() {
+                    return Err(content.unexpected(&[]));
+                }
+                self.cell.set(rest);
+                Ok((span, node))
+            }
+            None => Err(self.unexpected(&[delim.display()])),
+        }
+    }
+
+    pub fn parenthesized<T, F>(&self, parser: F) -> Result<(Span, T)>
+        where F: FnOnce(ParseStream) -> Result<T>
+    {
+        self.group(Delimiter::Parenthesis, parser)
+    }
+
+    pub fn braced<T, F>(&self, parser: F) -> Result<(Span, T)>
+        where F: FnOnce(ParseStream) -> Result<T>
+    {
+        self.group(Delimiter::Brace, parser)
+    }
+
+    pub fn bracketed<T, F>(&self, parser: F) -> Result<(Span, T)>
+        where F: FnOnce(ParseStream) -> Result<T>
+    {
+        self.group(Delimiter::Bracket, parser)
+    }
+
+    fn unexpected(&self, expected: &[String]) -> Error {
+        let mut message = if self.is_empty() {
+            "unexpected end of input".to_string()
+        } else {
+            "unexpected token".to_string()
+        };
+        match expected.len() {
+            0 => {}
+            1 => message.push_str(&format!(", expected {}", expected[0])),
+            _ => message.push_str(&format!(", expected one of: {}", expected.join(", "))),
+        }
+        self.error(message)
+    }
+}
+
+/// Support for checking the next token against several alternatives and
+/// producing an error listing all of them if none match.
+///
+/// ```ignore
+/// let lookahead = input.lookahead1();
+/// if lookahead.peek("struct") {
+///     ...
+/// } else if lookahead.peek("enum") {
+///     ...
+/// } else {
+///     return Err(lookahead.error());
+/// }
+/// ```
+pub struct Lookahead1<'a> {
+    cursor: Cursor<'a>,
+    comparisons: RefCell<Vec<String>>,
+}
+
+impl<'a> Lookahead1<'a> {
+    pub fn peek<T: Token>(&self, token: T) -> bool {
+        if token.step(self.cursor).is_some() {
+            return true;
+        }
+        self.comparisons.borrow_mut().push(token.display());
+        false
+    }
+
+    /// An error saying that none of the tokens peeked for were found.
+    pub fn error(self) -> Error {
+        ParseBuffer::new(self.cursor).unexpected(&self.comparisons.borrow())
+    }
+}
+
+impl Parse for Term {
+    fn parse(input: ParseStream) -> Result<Term> {
+        input.step(|cursor| match cursor.term() {
+            Some((term, _, rest)) => Ok((term.clone(), rest)),
+            None => Err(Error::new(cursor.span(), "expected identifier")),
+        })
+    }
+}
+
+impl Parse for Lifetime {
+    fn parse(input: ParseStream) -> Result<Lifetime> {
+        input.step(|cursor| match cursor.lifetime() {
+            Some((lifetime, _, rest)) => Ok((lifetime.clone(), rest)),
+            None => Err(Error::new(cursor.span(), "expected lifetime")),
+        })
+    }
+}
+
+impl Parse for Literal {
+    fn parse(input: ParseStream) -> Result<Literal> {
+        input.step(|cursor| match cursor.literal() {
+            Some((lit, _, rest)) => Ok((lit.clone(), rest)),
+            None => Err(Error::new(cursor.span(), "expected literal")),
+        })
+    }
+}
+
+impl Parse for TokenTree {
+    fn parse(input: ParseStream) -> Result<TokenTree> {
+        input.step(|cursor| match cursor.token_tree() {
+            Some(res) => Ok(res),
+            None => Err(Error::new(cursor.span(), "unexpected end of input")),
+        })
+    }
+}
+
+/// Consumes the rest of the input.
+impl Parse for TokenStream {
+    fn parse(input: ParseStream) -> Result<TokenStream> {
+        input.step(|cursor| {
+            let mut end = cursor;
+            while let Some(rest) = end.skip() {
+                end = rest;
+            }
+            Ok((cursor.token_stream(), end))
+        })
+    }
+}
+
+/// Parses all of `tokens` as a `T`, failing if any tokens are left over.
+pub fn parse<T: Parse>(tokens: TokenStream) -> Result<T> {
+    parse_with(tokens, T::parse)
+}
+
+/// Lexes `s` and parses all of it as a `T`.
+pub fn parse_str<T: Parse>(s: &str) -> Result<T> {
+    parse(s.parse()?)
+}
+
+/// Runs `parser` over all of `tokens`, failing if any tokens are left over.
+pub fn parse_with<T, F>(tokens: TokenStream, parser: F) -> Result<T>
+    where F: FnOnce(ParseStream) -> Result<T>
+{
+    let buffer = TokenBuffer::new(tokens);
+    let input = ParseBuffer::new(buffer.begin());
+    let node = parser(&input)?;
+    if input.is_empty() {
+        Ok(node)
+    } else {
+        Err(input.unexpected(&[]))
+    }
+}
+
+/// A sequence of `T` separated by some punctuation, optionally with a
+/// trailing separator.
+#[derive(Clone, Debug)]
+pub struct Punctuated<T> {
+    values: Vec<T>,
+    // The span of the separator following each value, if any.
+    separators: Vec<Span>,
+}
+
+impl<T> Punctuated<T> {
+    pub fn new() -> Punctuated<T> {
+        Punctuated {
+            values: Vec::new(),
+            separators: Vec::new(),
+        }
+    }
+
+    /// Parses zero or more `T` separated by `sep` until the end of `input`,
+    /// allowing a trailing separator.
+    pub fn parse_terminated<S, F>(input: ParseStream, sep: S, parser: F) -> Result<Punctuated<T>>
+        where S: Token + Copy,
+              F: Fn(ParseStream) -> Result<T>
+    {
+        let mut punctuated = Punctuated::new();
+        while !input.is_empty() {
+            punctuated.values.push(parser(input)?);
+            if input.is_empty() {
+                break;
+            }
+            punctuated.separators.push(input.expect(sep)?);
+        }
+        Ok(punctuated)
+    }
+
+    /// Parses one or more `T` separated by `sep`, stopping at the first value
+    /// not followed by a separator. No trailing separator is consumed.
+    pub fn parse_separated_nonempty<S, F>(input: ParseStream, sep: S, parser: F)
+        -> Result<Punctuated<T>>
+        where S: Token + Copy,
+              F: Fn(ParseStream) -> Result<T>
+    {
+        let mut punctuated = Punctuated::new();
+        loop {
+            punctuated.values.push(parser(input)?);
+            if !input.peek(sep) {
+                break;
+            }
+            punctuated.separators.push(input.expect(sep)?);
+        }
+        Ok(punctuated)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.values.iter()
+    }
+
+    /// The spans of the separators, in order.
+    pub fn separators(&self) -> &[Span] {
+        &self.separators
+    }
+
+    /// Whether the last value is followed by a separator.
+    pub fn trailing_punct(&self) -> bool {
+        !self.values.is_empty() && self.separators.len() == self.values.len()
+    }
+}
+
+impl<T> Default for Punctuated<T> {
+    fn default() -> Punctuated<T> {
+        Punctuated::new()
+    }
+}
+
+impl<T> IntoIterator for Punctuated<T> {
+    type Item = T;
+    type IntoIter = vec::IntoIter<T>;
+
+    fn into_iter(self) -> vec::IntoIter<T> {
+        self.values.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Punctuated<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
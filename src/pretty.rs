@@ -0,0 +1,403 @@
+//! Renders token streams as indented source for reading generated code,
+//! working purely from the tokens.
+//!
+//! The layout rules are heuristics in the spirit of rustfmt rather than a
+//! faithful reimplementation: braces open an indented block, `;` and `,`
+//! end a line inside blocks, attributes and doc comments get lines of their
+//! own, and operators are spaced according to whether they look unary or
+//! binary. A `<` opens a generic list after `::`, after the name of an
+//! item being defined, or after a name in a type, and is a comparison
+//! anywhere else. Types are recognised by what introduces them, such as `:`,
+//! `->`, `as` or `impl`, except that the `:` of a field in a struct
+//! expression is taken for one too, so `S { a: A < B }` comes out as
+//! `S { a: A<B }`.
+
+use std::mem;
+
+use buffer::{Cursor, TokenBuffer};
+use ident;
+use op;
+use {Delimiter, Edition, LiteralKind, TokenStream};
+
+const INDENT: &'static str = "    ";
+
+pub fn pretty(stream: &TokenStream) -> String {
+    let buffer = TokenBuffer::new(stream.clone());
+    let mut printer = Printer {
+        out: String::new(),
+        indent: 0,
+        line_start: true,
+        prev: Prev::Start,
+        generic_ok: false,
+        def_name: false,
+        attr: false,
+        scope: Scope::default(),
+    };
+    printer.stream(buffer.begin(), true);
+    if !printer.line_start {
+        printer.out.push('\n');
+    }
+    printer.out
+}
+
+// What was printed last, which decides the spacing before the next token.
+#[derive(Copy, Clone, PartialEq)]
+enum Prev {
+    // The start of a line or a group, where nothing needs separating.
+    Start,
+    // An identifier, literal, lifetime or `?`, and whether it was a keyword
+    // which is followed by a space even before a `(` or `[`.
+    Word(bool),
+    // A closing delimiter, including the `>` of a generic list.
+    Close,
+    // An operator which binds to the token after it, like unary `-`, `.`
+    // or `::`.
+    Tight,
+    // The `!` of a macro invocation.
+    Bang,
+    // A binary operator or one of `,` `;` `:`, followed by a space.
+    Spaced,
+}
+
+struct Printer {
+    out: String,
+    indent: usize,
+    line_start: bool,
+    prev: Prev,
+    // Whether a `<` right here would open a generic list.
+    generic_ok: bool,
+    // Whether the next word is the name of an item being defined.
+    def_name: bool,
+    // Whether we're between the `#` of an attribute and its brackets.
+    attr: bool,
+    scope: Scope,
+}
+
+// What the printer knows about the group it is in, which is set aside while
+// printing a nested group.
+#[derive(Default)]
+struct Scope {
+    // Whether we're in a type, so that a `<` after a name opens a generic
+    // list.
+    ty: bool,
+    // Whether the group holds types, as in `(A, B)` within a type, so that
+    // every `,` is followed by another.
+    types: bool,
+    // How many generic lists are open within the group.
+    generics: usize,
+    // Whether we were in a type before the outermost open generic list, as
+    // we are again once it closes.
+    outer_ty: bool,
+    // Whether the outermost open generic list follows `impl`, so that what
+    // comes after it is a separate type rather than part of a path.
+    impl_generics: bool,
+    // Whether we're between the `|`s of a closure's parameters.
+    closure: bool,
+    // The keyword of the item whose header we're in, such as the `struct`
+    // of `struct S<T>(T);` or the `type` of `type A = B<C>;`.
+    item: Option<&'static str>,
+    // Whether the group is the body of an enum, where the parentheses after
+    // a variant's name hold the types of its fields.
+    variants: bool,
+}
+
+impl Printer {
+    fn write(&mut self, s: &str, space: bool) {
+        if self.line_start {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+            self.line_start = false;
+        } else if space {
+            self.out.push(' ');
+        }
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        if !self.line_start {
+            self.out.push('\n');
+            self.line_start = true;
+        }
+        self.prev = Prev::Start;
+    }
+
+    // Whether `block` holds statements or items, rather than being the
+    // inside of parentheses or brackets.
+    fn stream(&mut self, mut cursor: Cursor, block: bool) {
+        while !cursor.eof() {
+            cursor = self.token(cursor, block);
+        }
+    }
+
+    fn token<'a>(&mut self, cursor: Cursor<'a>, block: bool) -> Cursor<'a> {
+        let attr = mem::replace(&mut self.attr, false);
+        let generic_ok = mem::replace(&mut self.generic_ok, false);
+        let def_name = mem::replace(&mut self.def_name, false);
+
+        if let Some((inside, delim, _, rest)) = cursor.any_group() {
+            self.group(inside, delim, block);
+            let ends_line = match delim {
+                Delimiter::Bracket => attr,
+                Delimiter::Brace => !continues(rest),
+                Delimiter::Parenthesis | Delimiter::None => false,
+            };
+            if ends_line && block {
+                self.newline();
+            }
+            return rest;
+        }
+
+        if let Some((term, _, rest)) = cursor.term() {
+            let name = term.as_str();
+            let keyword = !term.is_raw() && ident::is_keyword(name, Edition::Edition2024);
+            self.word(&term.to_string(), keyword && spaced_keyword(name));
+            if keyword {
+                self.keyword(name);
+            }
+            self.generic_ok = name == "impl" || (!keyword && (def_name || self.in_type()));
+            self.def_name = keyword && DEFINITIONS.contains(&name);
+            return rest;
+        }
+
+        if let Some((lifetime, _, rest)) = cursor.lifetime() {
+            self.word(lifetime.as_str(), false);
+            return rest;
+        }
+
+        if let Some((lit, _, rest)) = cursor.literal() {
+            self.word(&lit.to_string(), false);
+            // A doc comment runs to the end of the line.
+            if lit.kind() == LiteralKind::DocComment {
+                self.newline();
+            }
+            return rest;
+        }
+
+        match op::glue(cursor) {
+            Some((op, _, rest)) => {
+                self.op(op, block, attr, generic_ok);
+                rest
+            }
+            None => {
+                let (ch, _, _, rest) = cursor.op().unwrap();
+                self.op(&ch.to_string(), block, attr, generic_ok);
+                rest
+            }
+        }
+    }
+
+    fn word(&mut self, s: &str, keyword: bool) {
+        let space = match self.prev {
+            Prev::Start | Prev::Tight | Prev::Bang => false,
+            Prev::Word(_) | Prev::Close | Prev::Spaced => true,
+        };
+        self.write(s, space);
+        self.prev = Prev::Word(keyword);
+    }
+
+    // Keeps track of whether we're in a type across the keyword `name`.
+    fn keyword(&mut self, name: &str) {
+        match name {
+            "as" | "dyn" | "impl" | "where" => self.scope.ty = true,
+            // These carry on a type, as in `&mut T`, `impl X for T`,
+            // `unsafe extern fn()` or `self::T`.
+            "const" | "crate" | "extern" | "fn" | "for" | "mut" | "self" | "Self" |
+            "super" | "unsafe" => {}
+            _ => self.scope.ty = false,
+        }
+        if let Some(&item) = DEFINITIONS.iter().find(|&&item| item == name) {
+            self.scope.item = Some(item);
+        }
+    }
+
+    fn in_type(&self) -> bool {
+        self.scope.ty || self.scope.generics > 0
+    }
+
+    fn group(&mut self, inside: Cursor, delim: Delimiter, block: bool) {
+        let types = match delim {
+            Delimiter::Brace => false,
+            Delimiter::Parenthesis | Delimiter::Bracket | Delimiter::None => {
+                self.in_type() || self.scope.item == Some("struct") ||
+                    (self.scope.variants && self.prev == Prev::Word(false))
+            }
+        };
+        let inner = Scope {
+            ty: types,
+            types: types,
+            variants: delim == Delimiter::Brace && self.scope.item == Some("enum"),
+            ..Scope::default()
+        };
+        let outer = mem::replace(&mut self.scope, inner);
+        match delim {
+            // A group of `use` paths stays on one line, as in `a::{b, c}`.
+            Delimiter::Brace if self.prev == Prev::Tight => {
+                self.write("{", false);
+                self.prev = Prev::Start;
+                self.stream(inside, false);
+                self.write("}", false);
+                self.prev = Prev::Close;
+            }
+            Delimiter::Brace => {
+                let space = match self.prev {
+                    Prev::Start | Prev::Tight => false,
+                    Prev::Word(_) | Prev::Close | Prev::Bang | Prev::Spaced => true,
+                };
+                if inside.eof() {
+                    self.write("{}", space);
+                } else {
+                    self.write("{", space);
+                    self.indent += 1;
+                    self.newline();
+                    self.stream(inside, true);
+                    self.indent -= 1;
+                    self.newline();
+                    self.write("}", false);
+                }
+                self.prev = Prev::Close;
+            }
+            Delimiter::Parenthesis | Delimiter::Bracket => {
+                let (open, close) = if delim == Delimiter::Parenthesis {
+                    ("(", ")")
+                } else {
+                    ("[", "]")
+                };
+                let space = match self.prev {
+                    Prev::Start | Prev::Tight | Prev::Bang | Prev::Close => false,
+                    Prev::Word(keyword) => keyword,
+                    Prev::Spaced => true,
+                };
+                self.write(open, space);
+                self.prev = Prev::Start;
+                self.stream(inside, false);
+                self.write(close, false);
+                self.prev = Prev::Close;
+            }
+            Delimiter::None => self.stream(inside, block),
+        }
+        self.scope = outer;
+        // The body of an item, or a block, ends whatever came before it.
+        if delim == Delimiter::Brace {
+            self.scope.ty = false;
+            self.scope.item = None;
+        }
+    }
+
+    fn op(&mut self, op: &str, block: bool, attr: bool, generic_ok: bool) {
+        let prev = self.prev;
+        let space = match prev {
+            Prev::Start | Prev::Tight => false,
+            Prev::Word(_) | Prev::Close | Prev::Bang | Prev::Spaced => true,
+        };
+        let unary = match prev {
+            Prev::Start | Prev::Tight | Prev::Spaced | Prev::Word(true) => true,
+            Prev::Word(false) | Prev::Close | Prev::Bang => false,
+        };
+
+        let generics = self.scope.generics;
+        let (space, after) = match op {
+            "," | ";" | ":" => (false, Prev::Spaced),
+            "." | "::" | ".." | "..=" | "..." => {
+                (prev == Prev::Spaced || prev == Prev::Word(true), Prev::Tight)
+            }
+            "?" => (false, Prev::Word(false)),
+            "#" | "$" => (space, Prev::Tight),
+            "!" if prev == Prev::Word(false) => (false, Prev::Bang),
+            "<" if generic_ok || unary => {
+                if self.scope.generics == 0 {
+                    self.scope.impl_generics = prev == Prev::Word(true);
+                    self.scope.outer_ty = self.scope.ty;
+                }
+                self.scope.generics += 1;
+                (prev == Prev::Spaced, Prev::Tight)
+            }
+            ">" if self.scope.generics > 0 => {
+                self.scope.generics -= 1;
+                (false, self.close_generics())
+            }
+            ">>" if self.scope.generics > 1 => {
+                self.scope.generics -= 2;
+                (false, self.close_generics())
+            }
+            "|" if self.scope.closure => {
+                self.scope.closure = false;
+                (false, Prev::Spaced)
+            }
+            "|" if unary => {
+                self.scope.closure = true;
+                (space, Prev::Tight)
+            }
+            "||" if unary => (space, Prev::Spaced),
+            "!" | "~" | "&" | "&&" | "*" | "-" if unary => (space, Prev::Tight),
+            _ => (space, Prev::Spaced),
+        };
+        self.write(op, space);
+        self.prev = after;
+
+        match op {
+            ":" | "->" => self.scope.ty = true,
+            "=" => self.scope.ty = self.scope.item == Some("type"),
+            "," => self.scope.ty = self.scope.types,
+            ";" => {
+                self.scope.ty = false;
+                self.scope.item = None;
+            }
+            // Generic lists are taken care of above.
+            "<" | ">" | ">>" if self.scope.generics != generics => {}
+            // These carry on a type, as in `&&T`, `*const T`, `T: A + ?B`
+            // or `a::b::C`, and leave an expression as it was.
+            "&" | "&&" | "*" | "+" | "?" | "!" | "::" => {}
+            _ => self.scope.ty = false,
+        }
+        match op {
+            "#" if prev == Prev::Start => self.attr = true,
+            "!" if attr => self.attr = true,
+            ";" | "," if block && self.scope.generics == 0 && !self.scope.closure => {
+                self.newline()
+            }
+            _ => {}
+        }
+    }
+
+    fn close_generics(&mut self) -> Prev {
+        if self.scope.generics > 0 {
+            Prev::Close
+        } else if mem::replace(&mut self.scope.impl_generics, false) {
+            self.scope.ty = true;
+            Prev::Spaced
+        } else {
+            self.scope.ty = self.scope.outer_ty;
+            Prev::Close
+        }
+    }
+}
+
+// Keywords which introduce the name of an item, which may be followed by a
+// generic list.
+static DEFINITIONS: &'static [&'static str] = &["enum", "fn", "struct", "trait", "type", "union"];
+
+// Whether a space goes between the keyword `s` and a following `(` or `[`,
+// as in `if (a, b)`. Path keywords such as `self` are used like ordinary
+// names, and `fn(T)` is a type.
+fn spaced_keyword(s: &str) -> bool {
+    ident::can_be_raw(s) && s != "fn"
+}
+
+// Whether the token at `cursor` carries on the line after a closing brace,
+// as with `};`, `},` or `} else {`.
+fn continues(cursor: Cursor) -> bool {
+    if cursor.eof() {
+        return true;
+    }
+    if let Some((term, _, _)) = cursor.term() {
+        return !term.is_raw() && term.as_str() == "else";
+    }
+    match cursor.op() {
+        Some((';', _, _, _)) |
+        Some((',', _, _, _)) |
+        Some(('.', _, _, _)) |
+        Some(('?', _, _, _)) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,396 @@
+use std::iter;
+
+use imp;
+use {Lifetime, Literal, Term, TokenNode, TokenStream, TokenTree};
+
+/// Types that can be interpolated into a `quote!` invocation with `#var`.
+pub trait ToTokens {
+    /// Appends the tokens representing `self` to `tokens`.
+    fn to_tokens(&self, tokens: &mut TokenStream);
+
+    /// Collects the tokens representing `self` into a new stream.
+    fn to_token_stream(&self) -> TokenStream {
+        let mut tokens = TokenStream::empty();
+        self.to_tokens(&mut tokens);
+        tokens
+    }
+}
+
+impl<'a, T: ?Sized + ToTokens> ToTokens for &'a T {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        (**self).to_tokens(tokens);
+    }
+}
+
+impl<'a, T: ?Sized + ToTokens> ToTokens for &'a mut T {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        (**self).to_tokens(tokens);
+    }
+}
+
+impl<T: ?Sized + ToTokens> ToTokens for Box<T> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        (**self).to_tokens(tokens);
+    }
+}
+
+impl<T: ToTokens> ToTokens for Option<T> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        if let Some(ref t) = *self {
+            t.to_tokens(tokens);
+        }
+    }
+}
+
+impl<T: ToTokens> ToTokens for [T] {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        for t in self {
+            t.to_tokens(tokens);
+        }
+    }
+}
+
+impl<T: ToTokens> ToTokens for Vec<T> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self[..].to_tokens(tokens);
+    }
+}
+
+impl ToTokens for TokenStream {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.extend(iter::once(self.clone()));
+    }
+}
+
+impl ToTokens for TokenTree {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.extend(iter::once(self.clone()));
+    }
+}
+
+impl ToTokens for TokenNode {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        TokenTree::from(self.clone()).to_tokens(tokens);
+    }
+}
+
+impl ToTokens for Term {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        TokenNode::Term(self.clone()).to_tokens(tokens);
+    }
+}
+
+impl ToTokens for Lifetime {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        TokenNode::Lifetime(self.clone()).to_tokens(tokens);
+    }
+}
+
+impl ToTokens for Literal {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        TokenNode::Literal(self.clone()).to_tokens(tokens);
+    }
+}
+
+impl ToTokens for bool {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let repr = if *self { "true" } else { "false" };
+        Literal(imp::Literal::_new(repr)).to_tokens(tokens);
+    }
+}
+
+impl ToTokens for str {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        Literal::string(self).to_tokens(tokens);
+    }
+}
+
+impl ToTokens for String {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self[..].to_tokens(tokens);
+    }
+}
+
+impl ToTokens for char {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        Literal::character(*self).to_tokens(tokens);
+    }
+}
+
+macro_rules! primitives {
+    ($($t:ident)*) => ($(
+        impl ToTokens for $t {
+            fn to_tokens(&self, tokens: &mut TokenStream) {
+                Literal::$t(*self).to_tokens(tokens);
+            }
+        }
+    )*)
+}
+
+primitives! {
+    u8 u16 u32 u64 usize
+    i8 i16 i32 i64 isize
+    f32 f64
+}
+
+/// Support functions for the expansion of `quote!`. Not public API.
+#[doc(hidden)]
+pub mod __rt {
+    use imp;
+    use {Delimiter, Group, Lifetime, Literal, Spacing, Term, TokenNode, TokenStream, TokenTree};
+
+    fn push(tokens: &mut TokenStream, kind: TokenNode) {
+        tokens.extend(Some(TokenTree::from(kind)));
+    }
+
+    pub fn push_group(tokens: &mut TokenStream, delim: Delimiter, inner: TokenStream) {
+        push(tokens, TokenNode::Group(Group::new(delim, inner)));
+    }
+
+    pub fn push_term(tokens: &mut TokenStream, term: &str) {
+        // The lexer treats booleans as literals, so `quote!` does too.
+        if term == "true" || term == "false" {
+            return push_literal(tokens, term);
+        }
+        let term = if term.starts_with("r#") {
+            Term::intern_raw(&term[2..])
+        } else {
+            Term::intern(term)
+        };
+        push(tokens, TokenNode::Term(term));
+    }
+
+    pub fn push_lifetime(tokens: &mut TokenStream, lifetime: &str) {
+        push(tokens, TokenNode::Lifetime(Lifetime::new(lifetime)));
+    }
+
+    pub fn push_literal(tokens: &mut TokenStream, repr: &str) {
+        push(tokens, TokenNode::Literal(Literal(imp::Literal::_new(repr))));
+    }
+
+    /// Pushes a punctuation token from the macro input, which may be several
+    /// characters long like `<<=`, as a sequence of joint ops.
+    pub fn push_punct(tokens: &mut TokenStream, punct: &str) {
+        if punct == "_" {
+            return push_term(tokens, punct);
+        }
+        let mut chars = punct.chars().peekable();
+        while let Some(ch) = chars.next() {
+            let spacing = if chars.peek().is_some() {
+                Spacing::Joint
+            } else {
+                Spacing::Alone
+            };
+            push(tokens, TokenNode::Op(ch, spacing));
+        }
+    }
+}
+
+/// Builds a `TokenStream` from Rust syntax, interpolating variables which
+/// implement `ToTokens` with `#var`.
+///
+/// Repetition is written `#(...)*` or `#(...),*` (with any single-token
+/// separator); every `#var` inside the repetition must be an iterator (or
+/// `IntoIterator`) of `ToTokens` values, and they are advanced in lockstep.
+///
+/// ```ignore
+/// let name = Term::intern("Foo");
+/// let fields = vec![Term::intern("a"), Term::intern("b")];
+/// let tokens = quote! {
+///     impl #name {
+///         fn fields() -> &'static [&'static str] {
+///             &[#(stringify!(#fields)),*]
+///         }
+///     }
+/// };
+/// ```
+///
+/// The macro expands recursively, one token at a time, so large invocations
+/// may need a higher `#![recursion_limit]`.
+#[macro_export]
+macro_rules! quote {
+    () => {
+        $crate::TokenStream::empty()
+    };
+
+    ($($tt:tt)+) => {{
+        let mut _s = $crate::TokenStream::empty();
+        $crate::quote_each_token!(_s $($tt)*);
+        _s
+    }};
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! quote_each_token {
+    ($tokens:ident) => {};
+
+    ($tokens:ident # ( $($inner:tt)* ) * $($rest:tt)*) => {
+        for $crate::pounded_var_names!(nested_tuples_pat () $($inner)*)
+        in $crate::pounded_var_names!(multi_zip_expr () $($inner)*)
+        {
+            $crate::quote_each_token!($tokens $($inner)*);
+        }
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident # ( $($inner:tt)* ) $sep:tt * $($rest:tt)*) => {
+        for (_i, $crate::pounded_var_names!(nested_tuples_pat () $($inner)*))
+        in $crate::pounded_var_names!(multi_zip_expr () $($inner)*).into_iter().enumerate()
+        {
+            if _i > 0 {
+                $crate::quote_each_token!($tokens $sep);
+            }
+            $crate::quote_each_token!($tokens $($inner)*);
+        }
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident # $first:ident $($rest:tt)*) => {
+        $crate::ToTokens::to_tokens(&$first, &mut $tokens);
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident ( $($first:tt)* ) $($rest:tt)*) => {
+        $crate::__rt::push_group(&mut $tokens,
+                                 $crate::Delimiter::Parenthesis,
+                                 $crate::quote!($($first)*));
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident [ $($first:tt)* ] $($rest:tt)*) => {
+        $crate::__rt::push_group(&mut $tokens,
+                                 $crate::Delimiter::Bracket,
+                                 $crate::quote!($($first)*));
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident { $($first:tt)* } $($rest:tt)*) => {
+        $crate::__rt::push_group(&mut $tokens,
+                                 $crate::Delimiter::Brace,
+                                 $crate::quote!($($first)*));
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident $first:ident $($rest:tt)*) => {
+        $crate::__rt::push_term(&mut $tokens, stringify!($first));
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident $first:lifetime $($rest:tt)*) => {
+        $crate::__rt::push_lifetime(&mut $tokens, stringify!($first));
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    // Must come before `literal`, which would otherwise swallow the sign of
+    // `-1` into a single token.
+    ($tokens:ident - $($rest:tt)*) => {
+        $crate::__rt::push_punct(&mut $tokens, "-");
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident $first:literal $($rest:tt)*) => {
+        $crate::__rt::push_literal(&mut $tokens, stringify!($first));
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+
+    ($tokens:ident $first:tt $($rest:tt)*) => {
+        $crate::__rt::push_punct(&mut $tokens, stringify!($first));
+        $crate::quote_each_token!($tokens $($rest)*);
+    };
+}
+
+// Extracts the names of all `#var`s and passes them to `$finish`.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! pounded_var_names {
+    ($finish:ident ($($found:ident)*) # ( $($inner:tt)* ) $($rest:tt)*) => {
+        $crate::pounded_var_names!($finish ($($found)*) $($inner)* $($rest)*)
+    };
+
+    ($finish:ident ($($found:ident)*) # [ $($inner:tt)* ] $($rest:tt)*) => {
+        $crate::pounded_var_names!($finish ($($found)*) $($inner)* $($rest)*)
+    };
+
+    ($finish:ident ($($found:ident)*) # { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::pounded_var_names!($finish ($($found)*) $($inner)* $($rest)*)
+    };
+
+    ($finish:ident ($($found:ident)*) # $first:ident $($rest:tt)*) => {
+        $crate::pounded_var_names!($finish ($($found)* $first) $($rest)*)
+    };
+
+    ($finish:ident ($($found:ident)*) ( $($inner:tt)* ) $($rest:tt)*) => {
+        $crate::pounded_var_names!($finish ($($found)*) $($inner)* $($rest)*)
+    };
+
+    ($finish:ident ($($found:ident)*) [ $($inner:tt)* ] $($rest:tt)*) => {
+        $crate::pounded_var_names!($finish ($($found)*) $($inner)* $($rest)*)
+    };
+
+    ($finish:ident ($($found:ident)*) { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::pounded_var_names!($finish ($($found)*) $($inner)* $($rest)*)
+    };
+
+    ($finish:ident ($($found:ident)*) $ignore:tt $($rest:tt)*) => {
+        $crate::pounded_var_names!($finish ($($found)*) $($rest)*)
+    };
+
+    ($finish:ident ($($found:ident)*)) => {
+        $crate::$finish!(() $($found)*)
+    };
+}
+
+// in:   nested_tuples_pat!(() a b c d e)
+// out:  ((((a, b), c), d), e)
+//
+// in:   nested_tuples_pat!(() a)
+// out:  a
+#[macro_export]
+#[doc(hidden)]
+macro_rules! nested_tuples_pat {
+    (()) => {
+        &()
+    };
+
+    (() $first:ident $($rest:ident)*) => {
+        $crate::nested_tuples_pat!(($first) $($rest)*)
+    };
+
+    (($pat:pat) $first:ident $($rest:ident)*) => {
+        $crate::nested_tuples_pat!((($pat, $first)) $($rest)*)
+    };
+
+    (($done:pat)) => {
+        $done
+    };
+}
+
+// in:   multi_zip_expr!(() a b c d e)
+// out:  a.into_iter().zip(b).zip(c).zip(d).zip(e)
+//
+// in:   multi_zip_expr!(() a)
+// out:  a
+#[macro_export]
+#[doc(hidden)]
+macro_rules! multi_zip_expr {
+    (()) => {
+        &[]
+    };
+
+    (() $single:ident) => {
+        $single
+    };
+
+    (() $first:ident $($rest:ident)*) => {
+        $crate::multi_zip_expr!(($first.into_iter()) $($rest)*)
+    };
+
+    (($zips:expr) $first:ident $($rest:ident)*) => {
+        $crate::multi_zip_expr!(($zips.zip($first)) $($rest)*)
+    };
+
+    (($done:expr)) => {
+        $done
+    };
+}
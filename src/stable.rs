@@ -1,28 +1,139 @@
 use std::ascii;
 use std::borrow::Borrow;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::iter;
 use std::marker::PhantomData;
 use std::ops;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Weak};
+#[cfg(feature = "thread-safe")]
+use std::sync::{Mutex, PoisonError};
 use std::vec;
 
 use proc_macro;
 use unicode_xid::UnicodeXID;
-use strnom::{PResult, skip_whitespace, block_comment, whitespace, word_break};
+use strnom::{Cursor, PResult, skip_whitespace, block_comment, whitespace, word_break,
+             NONE_GROUP_OPEN, NONE_GROUP_CLOSE};
 
-use {TokenTree, TokenNode, Delimiter, Spacing};
+use {TokenTree, TokenNode, Delimiter, Spacing, LineColumn, LexErrorKind, LiteralKind, Edition};
+use ident;
+use lit;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TokenStream {
     inner: Vec<TokenTree>,
 }
 
-#[derive(Debug)]
-pub struct LexError;
+#[derive(Copy, Clone)]
+pub struct LexError {
+    kind: LexErrorKind,
+    off: u32,
+    expected: Option<&'static str>,
+    // Like spans, errors are located through the per-thread source map.
+    not_send_sync: PhantomData<ThreadBound>,
+}
+
+impl LexError {
+    pub fn new(input: Cursor, kind: LexErrorKind) -> LexError {
+        LexError {
+            kind: kind,
+            off: input.off,
+            expected: None,
+            not_send_sync: PhantomData,
+        }
+    }
+
+    /// The generic "this parser doesn't apply here" error, which loses to
+    /// any more specific error when alternatives are combined.
+    pub fn reject(input: Cursor) -> LexError {
+        LexError::new(input, LexErrorKind::UnexpectedCharacter)
+    }
+
+    fn expected(input: Cursor, kind: LexErrorKind, expected: &'static str) -> LexError {
+        LexError {
+            kind: kind,
+            off: input.off,
+            expected: Some(expected),
+            not_send_sync: PhantomData,
+        }
+    }
+
+    /// Picks the more informative of two errors from competing alternatives:
+    /// anything specific beats a plain reject, and otherwise the error which
+    /// got further into the input wins.
+    pub fn or(self, other: LexError) -> LexError {
+        let generic = LexErrorKind::UnexpectedCharacter;
+        if (self.kind == generic) != (other.kind == generic) {
+            if self.kind == generic { other } else { self }
+        } else if other.off > self.off {
+            other
+        } else {
+            self
+        }
+    }
+
+    pub fn kind(&self) -> LexErrorKind {
+        self.kind
+    }
+
+    pub fn expected_token(&self) -> Option<&str> {
+        self.expected
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.off, self.off)
+    }
+
+    pub fn offset(&self) -> usize {
+        with_source_map(|sm| {
+            match sm.fileinfo(self.span()) {
+                Some(fi) => (self.off - fi.span.lo) as usize,
+                None => 0,
+            }
+        })
+    }
+}
+
+impl fmt::Debug for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LexError")
+            .field("kind", &self.kind)
+            .field("off", &self.off)
+            .field("expected", &self.expected)
+            .finish()
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self.kind {
+            LexErrorKind::UnterminatedString => "unterminated string literal",
+            LexErrorKind::UnterminatedChar => "unterminated character literal",
+            LexErrorKind::UnterminatedComment => "unterminated block comment",
+            LexErrorKind::InvalidEscape => "invalid escape sequence",
+            LexErrorKind::InvalidSuffix => "invalid suffix for number literal",
+            LexErrorKind::InvalidNumber => "malformed number literal",
+            LexErrorKind::InvalidLifetime => "invalid lifetime",
+            LexErrorKind::InvalidRawIdent => "invalid raw identifier",
+            LexErrorKind::ReservedPrefix => "reserved prefix",
+            LexErrorKind::UnbalancedDelimiter => "unexpected closing delimiter",
+            LexErrorKind::UnclosedDelimiter => "unclosed delimiter",
+            LexErrorKind::UnexpectedCharacter => "unexpected character",
+            LexErrorKind::Other => "cannot parse string into token stream",
+        };
+        f.write_str(msg)?;
+        if let Some(expected) = self.expected {
+            write!(f, ", expected `{}`", expected)?;
+        }
+        let pos = self.span().start();
+        write!(f, " at line {}, column {}", pos.line, pos.column)
+    }
+}
 
 impl TokenStream {
     pub fn empty() -> TokenStream {
@@ -32,59 +143,103 @@ impl TokenStream {
     pub fn is_empty(&self) -> bool {
         self.inner.len() == 0
     }
+
+    pub fn parse_with_edition(src: &str, edition: Edition) -> Result<TokenStream, LexError> {
+        TokenStream::parse_source(None, src, edition, false)
+    }
+
+    pub fn parse_alternate(src: &str) -> Result<TokenStream, LexError> {
+        TokenStream::parse_source(None, src, Edition::default(), true)
+    }
+
+    pub fn parse_file(path: &Path, src: &str, edition: Edition) -> Result<TokenStream, LexError> {
+        TokenStream::parse_source(Some(path), src, edition, false)
+    }
+
+    fn parse_source(path: Option<&Path>, src: &str, edition: Edition, none_groups: bool)
+                    -> Result<TokenStream, LexError> {
+        // Register the string with the source map so the spans handed out below
+        // can be mapped back to lines and columns.
+        let cursor = with_source_map(|sm| {
+            let span = match path {
+                Some(path) => sm.add_file(path.to_owned(), path.is_file(), src),
+                None => {
+                    let name = format!("<parsed string {}>", sm.files.len());
+                    sm.add_file(PathBuf::from(name), false, src)
+                }
+            };
+            Cursor {
+                rest: src,
+                off: span.lo,
+                edition: edition,
+                none_groups: none_groups,
+            }
+        });
+
+        let (input, output) = token_stream(cursor)?;
+        let input = skip_whitespace(input);
+        if input.len() != 0 {
+            // `token_stream` only stops early at a closing delimiter.
+            Err(LexError::new(input, LexErrorKind::UnbalancedDelimiter))
+        } else {
+            Ok(output.0)
+        }
+    }
 }
 
 impl FromStr for TokenStream {
     type Err = LexError;
 
     fn from_str(src: &str) -> Result<TokenStream, LexError> {
-        match token_stream(src) {
-            Ok((input, output)) => {
-                if skip_whitespace(input).len() != 0 {
-                    Err(LexError)
-                } else {
-                    Ok(output.0)
-                }
-            }
-            Err(LexError) => Err(LexError),
-        }
+        TokenStream::parse_with_edition(src, Edition::default())
     }
 }
 
 impl fmt::Display for TokenStream {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut joint = false;
+        let mut joint = None;
         for (i, tt) in self.inner.iter().enumerate() {
-            if i != 0 && !joint {
-                write!(f, " ")?;
+            if i != 0 {
+                match joint {
+                    None => write!(f, " ")?,
+                    // A joint `/` can't be printed right before another `/`
+                    // or a `*`, since together they would start a comment.
+                    Some('/') if starts_with_comment_char(tt, f.alternate()) => write!(f, " ")?,
+                    Some(_) => {}
+                }
             }
-            joint = false;
+            joint = None;
             match tt.kind {
-                TokenNode::Group(delim, ref stream) => {
-                    let (start, end) = match delim {
+                TokenNode::Group(ref group) => {
+                    let stream = &group.stream;
+                    let (start, end) = match group.delimiter {
                         Delimiter::Parenthesis => ("(", ")"),
                         Delimiter::Brace => ("{", "}"),
                         Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None if f.alternate() => (NONE_GROUP_OPEN, NONE_GROUP_CLOSE),
                         Delimiter::None => ("", ""),
                     };
                     if stream.0.inner.len() == 0 {
                         write!(f, "{} {}", start, end)?
+                    } else if f.alternate() {
+                        write!(f, "{} {:#} {}", start, stream, end)?
                     } else {
                         write!(f, "{} {} {}", start, stream, end)?
                     }
                 }
-                TokenNode::Term(ref sym) => write!(f, "{}", sym.as_str())?,
+                TokenNode::Term(ref sym) => write!(f, "{}", sym)?,
+                TokenNode::Lifetime(ref lifetime) => write!(f, "{}", lifetime)?,
                 TokenNode::Op(ch, ref op) => {
                     write!(f, "{}", ch)?;
                     match *op {
                         Spacing::Alone => {}
-                        Spacing::Joint => joint = true,
+                        Spacing::Joint => joint = Some(ch),
                     }
                 }
                 TokenNode::Literal(ref literal) => {
                     write!(f, "{}", literal)?;
                     // handle comments
-                    if (literal.0).0.starts_with("/") {
+                    if literal.0.kind == LiteralKind::DocComment {
                         write!(f, "\n")?;
                     }
                 }
@@ -95,6 +250,22 @@ impl fmt::Display for TokenStream {
     }
 }
 
+fn starts_with_comment_char(tt: &TokenTree, alternate: bool) -> bool {
+    match tt.kind {
+        TokenNode::Op(ch, _) => ch == '/' || ch == '*',
+        TokenNode::Literal(ref literal) => literal.0.kind == LiteralKind::DocComment,
+        TokenNode::Group(ref group) if group.delimiter == Delimiter::None => {
+            alternate || match (group.stream.0).inner.first() {
+                Some(first) => starts_with_comment_char(first, alternate),
+                None => false,
+            }
+        }
+        TokenNode::Group(_) |
+        TokenNode::Term(_) |
+        TokenNode::Lifetime(_) => false,
+    }
+}
+
 impl From<proc_macro::TokenStream> for TokenStream {
     fn from(inner: proc_macro::TokenStream) -> TokenStream {
         inner.to_string().parse().expect("compiler token stream parse failed")
@@ -103,6 +274,7 @@ impl From<proc_macro::TokenStream> for TokenStream {
 
 impl From<TokenStream> for proc_macro::TokenStream {
     fn from(inner: TokenStream) -> proc_macro::TokenStream {
+        let inner = resolve_hygiene(::TokenStream(inner));
         inner.to_string().parse().expect("failed to parse to compiler tokens")
     }
 }
@@ -126,6 +298,20 @@ impl iter::FromIterator<TokenStream> for TokenStream {
     }
 }
 
+impl iter::Extend<TokenTree> for TokenStream {
+    fn extend<I: IntoIterator<Item=TokenTree>>(&mut self, trees: I) {
+        self.inner.extend(trees);
+    }
+}
+
+impl iter::Extend<TokenStream> for TokenStream {
+    fn extend<I: IntoIterator<Item=TokenStream>>(&mut self, streams: I) {
+        for stream in streams {
+            self.inner.extend(stream.inner);
+        }
+    }
+}
+
 pub type TokenTreeIter = vec::IntoIter<TokenTree>;
 
 impl IntoIterator for TokenStream {
@@ -137,27 +323,699 @@ impl IntoIterator for TokenStream {
     }
 }
 
-#[derive(Clone, Copy, Default, Debug)]
-pub struct Span;
+/// Stable has no way to report diagnostics to the compiler, so they are
+/// handed back as `compile_error!` tokens for the macro to return.
+pub fn emit_diagnostic(diag: ::Diagnostic) -> ::TokenStream {
+    diag.to_compile_error()
+}
+
+/// Stable output reaches the compiler as a string, which can't say which
+/// tokens were spanned at the def site, so local variables spanned there are
+/// given unique names here instead.
+///
+/// Each call counts as a separate expansion: a name is given the same
+/// replacement everywhere within one call, and different replacements in
+/// different calls.
+pub fn resolve_hygiene(stream: ::TokenStream) -> ::TokenStream {
+    let mut names = Names {
+        taken: HashSet::new(),
+        locals: HashSet::new(),
+        lifetimes: HashSet::new(),
+    };
+    names.collect(&stream);
+    let expansion = EXPANSIONS.with(|n| {
+        let expansion = n.get();
+        n.set(expansion + 1);
+        expansion
+    });
+    let mut renamer = Renamer {
+        expansion: expansion,
+        names: names,
+        renamed: HashMap::new(),
+    };
+    renamer.stream(stream)
+}
+
+thread_local!(static EXPANSIONS: Cell<usize> = Cell::new(0));
+
+fn is_def_site(span: ::Span) -> bool {
+    span.0.ctxt == SyntaxContext::DefSite
+}
+
+struct Names {
+    // Every name in the stream, which the new names must not clash with.
+    taken: HashSet<String>,
+    // The def_site names which are bound as local variables.
+    locals: HashSet<String>,
+    // The def_site lifetimes and labels which are declared in the stream.
+    lifetimes: HashSet<String>,
+}
+
+impl Names {
+    fn collect(&mut self, stream: &::TokenStream) {
+        let trees = stream.clone().into_iter().collect::<Vec<_>>();
+        // Whether we're between `impl` and the body, where `for` comes before
+        // a type rather than a pattern.
+        let mut impl_header = false;
+        for (i, tt) in trees.iter().enumerate() {
+            match tt.kind {
+                TokenNode::Group(ref group) => {
+                    self.collect(&group.stream);
+                    if group.delimiter == Delimiter::Brace {
+                        impl_header = false;
+                    }
+                }
+                TokenNode::Term(ref term) => {
+                    self.taken.insert(term.as_str().to_owned());
+                    if is_def_site(tt.span) && renameable(term) &&
+                       is_binding(&trees, i, impl_header) {
+                        self.locals.insert(term.as_str().to_owned());
+                    }
+                    if is_keyword(term, "impl") {
+                        impl_header = true;
+                    }
+                }
+                TokenNode::Lifetime(ref lifetime) => {
+                    self.taken.insert(lifetime.to_string());
+                    if is_def_site(tt.span) && is_declaration(&trees, i) {
+                        self.lifetimes.insert(lifetime.to_string());
+                    }
+                }
+                TokenNode::Op(';', _) => impl_header = false,
+                TokenNode::Op(..) | TokenNode::Literal(_) => {}
+            }
+        }
+    }
+}
+
+// Whether the term at `i` is a whole pattern on its own, as in `let x`,
+// `for x in` or `ref mut x`. Names within larger patterns are missed, since
+// telling them apart from the paths in a pattern takes more than the tokens.
+fn is_binding(trees: &[TokenTree], i: usize, impl_header: bool) -> bool {
+    let kind = |i: Option<usize>| i.and_then(|i| trees.get(i)).map(|tt| &tt.kind);
+    let starts = match kind(i.checked_sub(1)) {
+        Some(&TokenNode::Term(ref term)) if is_keyword(term, "let") => true,
+        Some(&TokenNode::Term(ref term)) if is_keyword(term, "for") => !impl_header,
+        Some(&TokenNode::Term(ref term)) if is_keyword(term, "mut") || is_keyword(term, "ref") => {
+            // Not in a type or expression like `*mut T`, `&'a mut T` or
+            // `&mut x`, nor a `static mut`.
+            match kind(i.checked_sub(2)) {
+                Some(&TokenNode::Op('&', _)) |
+                Some(&TokenNode::Op('*', _)) |
+                Some(&TokenNode::Lifetime(_)) => false,
+                Some(&TokenNode::Term(ref term)) => !is_keyword(term, "static"),
+                _ => true,
+            }
+        }
+        _ => false,
+    };
+    let ends = match kind(Some(i + 1)) {
+        // A path like `a::b` or a macro call like `m!()`.
+        Some(&TokenNode::Op(':', Spacing::Joint)) | Some(&TokenNode::Op('!', _)) => false,
+        Some(&TokenNode::Op(..)) | None => true,
+        Some(&TokenNode::Term(ref term)) => is_keyword(term, "in"),
+        Some(&TokenNode::Group(_)) |
+        Some(&TokenNode::Lifetime(_)) |
+        Some(&TokenNode::Literal(_)) => false,
+    };
+    starts && ends
+}
+
+// Whether the lifetime at `i` is declared there: as a parameter at the top
+// level of the generics of an item, an `impl` or a `for<...>`, as in
+// `fn f<'a, T: 'a>`, or as the label of a loop or block, as in `'a: loop`.
+// `'static` and `'_` can't be declared, so they're never renamed.
+fn is_declaration(trees: &[TokenTree], i: usize) -> bool {
+    let kind = |i: Option<usize>| i.and_then(|i| trees.get(i)).map(|tt| &tt.kind);
+    match (kind(Some(i + 1)), kind(Some(i + 2))) {
+        (Some(&TokenNode::Op(':', Spacing::Alone)), Some(&TokenNode::Group(ref group)))
+            if group.delimiter == Delimiter::Brace => return true,
+        (Some(&TokenNode::Op(':', Spacing::Alone)), Some(&TokenNode::Term(ref term)))
+            if ["loop", "while", "for"].iter().any(|kw| is_keyword(term, kw)) => return true,
+        _ => {}
+    }
+    // Walk back to the `<` opening the list this lifetime is a parameter in,
+    // if it is one.
+    let mut depth = 0;
+    let mut j = i;
+    while let Some(prev) = j.checked_sub(1) {
+        match trees[prev].kind {
+            TokenNode::Op('<', _) if depth == 0 => break,
+            TokenNode::Op('<', _) => depth -= 1,
+            // Not the `>` of `->`.
+            TokenNode::Op('>', _) if kind(prev.checked_sub(1)) !=
+                                     Some(&TokenNode::Op('-', Spacing::Joint)) => depth += 1,
+            TokenNode::Op(';', _) | TokenNode::Op('=', _) => return false,
+            _ => {}
+        }
+        j = prev;
+    }
+    let open = match j.checked_sub(1) {
+        Some(open) if depth == 0 => open,
+        _ => return false,
+    };
+    // Only the first token of a parameter is declared, not the bounds after
+    // it in `<'a, 'b: 'a>`.
+    match trees[i - 1].kind {
+        TokenNode::Op('<', _) if i - 1 == open => {}
+        TokenNode::Op(',', _) => {}
+        _ => return false,
+    }
+    match (kind(open.checked_sub(1)), kind(open.checked_sub(2))) {
+        (Some(&TokenNode::Term(ref term)), _) if is_keyword(term, "impl") ||
+                                                 is_keyword(term, "for") => true,
+        (Some(&TokenNode::Term(_)), Some(&TokenNode::Term(ref item))) => {
+            ["fn", "struct", "enum", "union", "trait", "type"].iter()
+                .any(|kw| is_keyword(item, kw))
+        }
+        _ => false,
+    }
+}
+
+// Whether the term at `i` names a field, method or path segment, as in
+// `v.len()`, `self.x` or `a::b`, rather than a variable. A `..` before it is
+// a range, as in `0..n`.
+fn is_member(trees: &[TokenTree], i: usize) -> bool {
+    let kind = |i: Option<usize>| i.and_then(|i| trees.get(i)).map(|tt| &tt.kind);
+    match (kind(i.checked_sub(1)), kind(i.checked_sub(2))) {
+        (Some(&TokenNode::Op('.', _)), Some(&TokenNode::Op('.', Spacing::Joint))) => false,
+        (Some(&TokenNode::Op('.', _)), _) => true,
+        (Some(&TokenNode::Op(':', Spacing::Alone)), Some(&TokenNode::Op(':', Spacing::Joint))) => {
+            true
+        }
+        _ => false,
+    }
+}
+
+// Whether the brace group at `i` is a list of named fields: the body of a
+// struct literal or pattern, as in `S { x }` or `S::<T> { x: 1 }`, or of a
+// struct, enum or union. There is a path before each of those, but also
+// before a block after the condition of an `if`, `while` or `match`, or after
+// the signature of a `fn`, so the tokens back to the start of the statement
+// are checked for the keyword which begins it.
+fn has_fields(trees: &[TokenTree], i: usize) -> bool {
+    match i.checked_sub(1).map(|i| &trees[i].kind) {
+        Some(&TokenNode::Term(ref term)) if renameable(term) || is_keyword(term, "Self") => {}
+        Some(&TokenNode::Op('>', _)) => {}
+        _ => return false,
+    }
+    for tt in trees[..i].iter().rev() {
+        match tt.kind {
+            TokenNode::Op(';', _) => break,
+            TokenNode::Group(ref group) if group.delimiter == Delimiter::Brace => break,
+            TokenNode::Term(ref term) => {
+                if ["struct", "enum", "union"].iter().any(|kw| is_keyword(term, kw)) {
+                    return true;
+                }
+                let blocks = ["if", "while", "match", "in", "fn", "impl", "trait", "mod"];
+                if blocks.iter().any(|kw| is_keyword(term, kw)) {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+// Whether the term at `i` in a list of fields is a whole field on its own,
+// as in `S { x }` or `S { a, x, ..s }`.
+fn is_shorthand(trees: &[TokenTree], i: usize) -> bool {
+    let starts = match i.checked_sub(1).map(|i| &trees[i].kind) {
+        None | Some(&TokenNode::Op(',', _)) => true,
+        _ => false,
+    };
+    let ends = match trees.get(i + 1).map(|tt| &tt.kind) {
+        None | Some(&TokenNode::Op(',', _)) => true,
+        _ => false,
+    };
+    starts && ends
+}
+
+// Whether the term at `i` in a list of fields is the name of a field, as in
+// `S { x: 1 }` or `struct S { x: u8 }`.
+fn is_field_name(trees: &[TokenTree], i: usize) -> bool {
+    let starts = match i.checked_sub(1).map(|i| &trees[i].kind) {
+        None | Some(&TokenNode::Op(',', _)) => true,
+        _ => false,
+    };
+    let ends = match trees.get(i + 1).map(|tt| &tt.kind) {
+        Some(&TokenNode::Op(':', Spacing::Alone)) => true,
+        _ => false,
+    };
+    starts && ends
+}
+
+fn is_keyword(term: &::Term, keyword: &str) -> bool {
+    !term.is_raw() && term.as_str() == keyword
+}
+
+struct Renamer {
+    expansion: usize,
+    names: Names,
+    // The replacement chosen for each name so far.
+    renamed: HashMap<String, String>,
+}
+
+impl Renamer {
+    fn stream(&mut self, stream: ::TokenStream) -> ::TokenStream {
+        self.trees(stream, false)
+    }
+
+    // Renames the trees of one group, which is a list of named fields if
+    // `fields` is set.
+    fn trees(&mut self, stream: ::TokenStream, fields: bool) -> ::TokenStream {
+        let trees = stream.into_iter().collect::<Vec<_>>();
+        let mut renamed = Vec::new();
+        for (i, tt) in trees.iter().enumerate() {
+            let def_site = is_def_site(tt.span);
+            let kind = match tt.kind {
+                TokenNode::Group(ref group) => {
+                    let fields = group.delimiter == Delimiter::Brace && has_fields(&trees, i);
+                    TokenNode::Group(::Group {
+                        stream: self.trees(group.stream.clone(), fields),
+                        ..group.clone()
+                    })
+                }
+                TokenNode::Term(ref term) if def_site &&
+                                             self.names.locals.contains(term.as_str()) &&
+                                             !is_member(&trees, i) &&
+                                             !(fields && is_field_name(&trees, i)) => {
+                    if fields && is_shorthand(&trees, i) {
+                        // `S { x }` becomes `S { x: __h0_x }`, keeping the
+                        // field name.
+                        renamed.push(tt.clone());
+                        renamed.push(TokenTree {
+                            span: tt.span,
+                            kind: TokenNode::Op(':', Spacing::Alone),
+                        });
+                    }
+                    TokenNode::Term(::Term::intern(&self.rename(term.as_str())))
+                }
+                TokenNode::Lifetime(ref lifetime)
+                    if def_site && self.names.lifetimes.contains(&lifetime.to_string()) => {
+                    TokenNode::Lifetime(::Lifetime::new(&self.rename(&lifetime.to_string())))
+                }
+                ref kind => kind.clone(),
+            };
+            renamed.push(TokenTree {
+                span: tt.span,
+                kind: kind,
+            });
+        }
+        renamed.into_iter().collect()
+    }
+
+    fn rename(&mut self, name: &str) -> String {
+        if let Some(renamed) = self.renamed.get(name) {
+            return renamed.clone();
+        }
+        let mut renamed = if name.starts_with('\'') {
+            format!("'__h{}_{}", self.expansion, &name[1..])
+        } else {
+            format!("__h{}_{}", self.expansion, name)
+        };
+        while self.names.taken.contains(&renamed) {
+            renamed.push('_');
+        }
+        self.names.taken.insert(renamed.clone());
+        self.renamed.insert(name.to_owned(), renamed.clone());
+        renamed
+    }
+}
+
+// Keywords, and words like `self` which always refer to the same thing,
+// can't be bound by a pattern so they're never renamed.
+fn renameable(term: &::Term) -> bool {
+    let name = term.as_str();
+    term.is_raw() || !ident::is_keyword(name, Edition::Edition2024) && ident::can_be_raw(name)
+}
+
+#[cfg(not(feature = "thread-safe"))]
+fn with_source_map<F, R>(f: F) -> R
+    where F: FnOnce(&mut SourceMap) -> R
+{
+    thread_local!(static SOURCE_MAP: RefCell<SourceMap> = RefCell::new(SourceMap::new()));
+    SOURCE_MAP.with(|sm| f(&mut sm.borrow_mut()))
+}
+
+// Spans may be sent to other threads along with their tokens, so they need
+// a single map to look themselves up in.
+#[cfg(feature = "thread-safe")]
+fn with_source_map<F, R>(f: F) -> R
+    where F: FnOnce(&mut SourceMap) -> R
+{
+    static SOURCE_MAP: Mutex<Option<SourceMap>> = Mutex::new(None);
+    let mut sm = SOURCE_MAP.lock().unwrap_or_else(PoisonError::into_inner);
+    f(sm.get_or_insert_with(SourceMap::new))
+}
+
+struct FileInfo {
+    path: PathBuf,
+    // Whether `path` names a file on disk, rather than describing where a
+    // parsed string came from.
+    is_real: bool,
+    span: Span,
+    lines: Vec<usize>,
+    // The text which was parsed, or `None` for the dummy file.
+    source: Option<String>,
+}
+
+impl FileInfo {
+    fn offset_line_column(&self, offset: usize) -> LineColumn {
+        assert!(self.span_within(Span::new(offset as u32, offset as u32)));
+        let offset = offset - self.span.lo as usize;
+        match self.lines.binary_search(&offset) {
+            Ok(found) => LineColumn { line: found + 1, column: 0 },
+            Err(idx) => LineColumn { line: idx, column: offset - self.lines[idx - 1] },
+        }
+    }
+
+    fn span_within(&self, span: Span) -> bool {
+        span.lo >= self.span.lo && span.hi <= self.span.hi
+    }
+
+    fn source_text(&self, span: Span) -> Option<&str> {
+        let lo = (span.lo - self.span.lo) as usize;
+        let hi = (span.hi - self.span.lo) as usize;
+        self.source.as_ref().map(|source| &source[lo..hi])
+    }
+
+    fn source_file(&self) -> SourceFile {
+        SourceFile {
+            path: self.path.clone(),
+            is_real: self.is_real,
+            span: self.span,
+        }
+    }
+}
+
+/// Computes the byte offsets of the start of each line in `s`.
+fn lines_offsets(s: &str) -> Vec<usize> {
+    let mut lines = vec![0];
+    let mut prev = 0;
+    while let Some(len) = s[prev..].find('\n') {
+        prev += len + 1;
+        lines.push(prev);
+    }
+    lines
+}
+
+struct SourceMap {
+    files: Vec<FileInfo>,
+    // Where the next file starts. Files freed by `with_interner` leave a gap
+    // behind, so that spans into them never point into a later file.
+    next_pos: u32,
+    // The number of files there were when each active `with_interner` scope
+    // was entered, innermost last.
+    #[cfg(not(feature = "thread-safe"))]
+    scopes: Vec<usize>,
+}
+
+impl SourceMap {
+    fn new() -> SourceMap {
+        SourceMap {
+            // NOTE: We start with a single dummy file which all call_site()
+            // spans reference.
+            files: vec![FileInfo {
+                path: PathBuf::from("<unspecified>"),
+                is_real: false,
+                span: Span::new(0, 0),
+                lines: vec![0],
+                source: None,
+            }],
+            next_pos: 1,
+            #[cfg(not(feature = "thread-safe"))]
+            scopes: Vec::new(),
+        }
+    }
+
+    fn add_file(&mut self, path: PathBuf, is_real: bool, src: &str) -> Span {
+        let lo = self.next_pos;
+        // XXX: Should we bother doing a checked cast or checked add here?
+        let span = Span::new(lo, lo + (src.len() as u32));
+        // Add 1 so there's always space between files.
+        self.next_pos = span.hi + 1;
+
+        self.files.push(FileInfo {
+            path: path,
+            is_real: is_real,
+            span: span,
+            lines: lines_offsets(src),
+            source: Some(src.to_owned()),
+        });
+
+        span
+    }
+
+    // The file `span` points into, or `None` if it has been freed.
+    fn fileinfo(&self, span: Span) -> Option<&FileInfo> {
+        self.files.iter().find(|file| file.span_within(span))
+    }
+
+    #[cfg(not(feature = "thread-safe"))]
+    fn enter(&mut self) {
+        self.scopes.push(self.files.len());
+    }
+
+    #[cfg(not(feature = "thread-safe"))]
+    fn exit(&mut self) {
+        let len = self.scopes.pop().unwrap();
+        self.files.truncate(len);
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    path: PathBuf,
+    is_real: bool,
+    // The file's range in the source map, which tells apart separate
+    // strings parsed under the same path.
+    span: Span,
+}
+
+impl SourceFile {
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    pub fn is_real(&self) -> bool {
+        self.is_real
+    }
+}
+
+impl fmt::Debug for SourceFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SourceFile")
+            .field("path", &self.path)
+            .field("is_real", &self.is_real)
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    lo: u32,
+    hi: u32,
+    ctxt: SyntaxContext,
+    // Offsets are only meaningful to the source map of the thread which
+    // handed them out.
+    not_send_sync: PhantomData<ThreadBound>,
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Span")
+            .field("lo", &self.lo)
+            .field("hi", &self.hi)
+            .field("ctxt", &self.ctxt)
+            .finish()
+    }
+}
+
+// Where the names in a span resolve. There's no compiler to resolve them on
+// stable, so this only keeps spans from different contexts apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum SyntaxContext {
+    CallSite,
+    MixedSite,
+    DefSite,
+}
+
+impl Default for SyntaxContext {
+    fn default() -> SyntaxContext {
+        SyntaxContext::CallSite
+    }
+}
 
 impl Span {
+    fn new(lo: u32, hi: u32) -> Span {
+        Span {
+            lo: lo,
+            hi: hi,
+            ctxt: SyntaxContext::CallSite,
+            not_send_sync: PhantomData,
+        }
+    }
+
     pub fn call_site() -> Span {
-        Span
+        Span::new(0, 0)
+    }
+
+    pub fn def_site() -> Span {
+        Span { ctxt: SyntaxContext::DefSite, ..Span::call_site() }
+    }
+
+    pub fn mixed_site() -> Span {
+        Span { ctxt: SyntaxContext::MixedSite, ..Span::call_site() }
+    }
+
+    pub fn resolved_at(&self, other: Span) -> Span {
+        Span { ctxt: other.ctxt, ..*self }
+    }
+
+    pub fn located_at(&self, other: Span) -> Span {
+        other.resolved_at(*self)
+    }
+
+    // A span into a file freed by `with_interner` is treated like one from
+    // `call_site`, which points into the dummy file.
+
+    pub fn start(&self) -> LineColumn {
+        with_source_map(|sm| {
+            match sm.fileinfo(*self) {
+                Some(fi) => fi.offset_line_column(self.lo as usize),
+                None => sm.files[0].offset_line_column(0),
+            }
+        })
+    }
+
+    pub fn end(&self) -> LineColumn {
+        with_source_map(|sm| {
+            match sm.fileinfo(*self) {
+                Some(fi) => fi.offset_line_column(self.hi as usize),
+                None => sm.files[0].offset_line_column(0),
+            }
+        })
+    }
+
+    pub fn source_text(&self) -> Option<String> {
+        with_source_map(|sm| {
+            sm.fileinfo(*self).and_then(|fi| fi.source_text(*self)).map(str::to_owned)
+        })
+    }
+
+    pub fn source_file(&self) -> SourceFile {
+        with_source_map(|sm| {
+            sm.fileinfo(*self).unwrap_or(&sm.files[0]).source_file()
+        })
+    }
+
+    pub fn join(&self, other: Span) -> Option<Span> {
+        with_source_map(|sm| {
+            match sm.fileinfo(*self) {
+                Some(fi) if fi.span_within(other) => {}
+                _ => return None,
+            }
+            Some(Span {
+                lo: cmp::min(self.lo, other.lo),
+                hi: cmp::max(self.hi, other.hi),
+                ctxt: self.ctxt,
+                not_send_sync: PhantomData,
+            })
+        })
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Term {
-    intern: usize,
-    not_send_sync: PhantomData<*const ()>,
+    // Shared with the interner and with the other terms of the same name, and
+    // freed along with the last of them once the interner has dropped it.
+    string: Arc<String>,
+    raw: bool,
+    not_send_sync: PhantomData<ThreadBound>,
 }
 
-thread_local!(static SYMBOLS: RefCell<Interner> = RefCell::new(Interner::new()));
+// Spans and lex errors index into a per-thread source map, so none of them
+// can leave their thread unless the `thread-safe` feature makes it global.
+// Terms are kept to their thread alongside them, like the compiler's own.
+#[cfg(not(feature = "thread-safe"))]
+type ThreadBound = *const ();
+#[cfg(feature = "thread-safe")]
+type ThreadBound = ();
+
+#[cfg(not(feature = "thread-safe"))]
+fn with_symbols<F, R>(f: F) -> R
+    where F: FnOnce(&mut Interner) -> R
+{
+    thread_local!(static SYMBOLS: RefCell<Interner> = RefCell::new(Interner::new()));
+    SYMBOLS.with(|s| f(&mut s.borrow_mut()))
+}
+
+#[cfg(feature = "thread-safe")]
+fn with_symbols<F, R>(f: F) -> R
+    where F: FnOnce(&mut Interner) -> R
+{
+    static SYMBOLS: Mutex<Option<Interner>> = Mutex::new(None);
+    let mut s = SYMBOLS.lock().unwrap_or_else(PoisonError::into_inner);
+    f(s.get_or_insert_with(Interner::new))
+}
+
+// The strings first interned within each active `with_interner` scope on this
+// thread, innermost last. With the `thread-safe` feature the interner is
+// shared, but each thread still only drops what its own scopes interned.
+thread_local!(static SCOPES: RefCell<Vec<Vec<Arc<String>>>> = RefCell::new(Vec::new()));
+
+/// Runs `f`, then drops every symbol which was first interned while it ran
+/// from the interner, along with the source files lexed in it.
+pub fn with_interner<F, R>(f: F) -> R
+    where F: FnOnce() -> R
+{
+    // Leave the scope even if `f` panics.
+    struct Scope;
+
+    impl Drop for Scope {
+        fn drop(&mut self) {
+            let strings = SCOPES.with(|scopes| scopes.borrow_mut().pop().unwrap());
+            with_symbols(|s| s.exit(strings));
+            // Other threads may hold spans into any file in the global
+            // source map, so with the `thread-safe` feature it's kept.
+            #[cfg(not(feature = "thread-safe"))]
+            with_source_map(|sm| sm.exit());
+        }
+    }
+
+    SCOPES.with(|scopes| scopes.borrow_mut().push(Vec::new()));
+    #[cfg(not(feature = "thread-safe"))]
+    with_source_map(|sm| sm.enter());
+    let _scope = Scope;
+    f()
+}
+
+pub fn interner_stats() -> Option<::interner::Stats> {
+    Some(with_symbols(|s| s.stats()))
+}
+
+impl Term {
+    /// Interns `string` as a raw identifier, which is printed with an `r#`
+    /// prefix.
+    pub fn raw(string: &str) -> Term {
+        Term {
+            raw: true,
+            ..Term::from(string)
+        }
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.raw
+    }
+}
 
 impl<'a> From<&'a str> for Term {
     fn from(string: &'a str) -> Term {
         Term {
-            intern: SYMBOLS.with(|s| s.borrow_mut().intern(string)),
+            string: with_symbols(|s| s.intern(string)),
+            raw: false,
             not_send_sync: PhantomData,
         }
     }
@@ -167,29 +1025,47 @@ impl ops::Deref for Term {
     type Target = str;
 
     fn deref(&self) -> &str {
-        SYMBOLS.with(|interner| {
-            let interner = interner.borrow();
-            let s = interner.get(self.intern);
-            unsafe {
-                &*(s as *const str)
-            }
-        })
+        &self.string
+    }
+}
+
+// Terms of the same name usually share a string, but not when one of them
+// was interned again after `with_interner` dropped the other's.
+impl PartialEq for Term {
+    fn eq(&self, other: &Term) -> bool {
+        self.raw == other.raw &&
+            (Arc::ptr_eq(&self.string, &other.string) || self.string == other.string)
+    }
+}
+
+impl Eq for Term {}
+
+impl Hash for Term {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.string.hash(state);
+        self.raw.hash(state);
     }
 }
 
 impl fmt::Debug for Term {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_tuple("Term").field(&&**self).finish()
+        if self.raw {
+            f.debug_tuple("Term").field(&format!("r#{}", &**self)).finish()
+        } else {
+            f.debug_tuple("Term").field(&&**self).finish()
+        }
     }
 }
 
 struct Interner {
-    string_to_index: HashMap<MyRc, usize>,
-    index_to_string: Vec<Rc<String>>,
+    strings: HashSet<MyRc>,
+    // Strings which `with_interner` has dropped but which terms held at the
+    // time, and may still.
+    released: Vec<Weak<String>>,
 }
 
 #[derive(Hash, Eq, PartialEq)]
-struct MyRc(Rc<String>);
+struct MyRc(Arc<String>);
 
 impl Borrow<str> for MyRc {
     fn borrow(&self) -> &str {
@@ -200,39 +1076,77 @@ impl Borrow<str> for MyRc {
 impl Interner {
     fn new() -> Interner {
         Interner {
-            string_to_index: HashMap::new(),
-            index_to_string: Vec::new(),
+            strings: HashSet::new(),
+            released: Vec::new(),
+        }
+    }
+
+    fn intern(&mut self, s: &str) -> Arc<String> {
+        if let Some(s) = self.strings.get(s) {
+            return s.0.clone();
         }
+        let s = Arc::new(s.to_string());
+        self.strings.insert(MyRc(s.clone()));
+        SCOPES.with(|scopes| {
+            if let Some(scope) = scopes.borrow_mut().last_mut() {
+                scope.push(s.clone());
+            }
+        });
+        s
     }
 
-   fn intern(&mut self, s: &str) -> usize {
-        if let Some(&idx) = self.string_to_index.get(s) {
-            return idx
+    fn exit(&mut self, strings: Vec<Arc<String>>) {
+        for s in strings {
+            self.strings.remove(&s[..]);
+            // Any other references are held by terms.
+            if Arc::strong_count(&s) > 1 {
+                self.released.push(Arc::downgrade(&s));
+            }
         }
-        let s = Rc::new(s.to_string());
-        self.index_to_string.push(s.clone());
-        self.string_to_index.insert(MyRc(s), self.index_to_string.len() - 1);
-        self.index_to_string.len() - 1
+        self.released.retain(|s| s.upgrade().is_some());
     }
 
-   fn get(&self, idx: usize) -> &str {
-       &self.index_to_string[idx]
-   }
+    fn stats(&self) -> ::interner::Stats {
+        ::interner::Stats {
+            symbols: self.strings.len(),
+            bytes: self.strings.iter().map(|s| s.0.len()).sum(),
+            kept: self.released.iter().filter_map(Weak::upgrade).map(|s| s.len()).sum(),
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
-pub struct Literal(String);
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Literal {
+    text: String,
+    kind: LiteralKind,
+}
 
 impl Literal {
+    fn new(text: String, kind: LiteralKind) -> Literal {
+        Literal {
+            text: text,
+            kind: kind,
+        }
+    }
+
+    /// Creates a literal from its source text, which must already be valid.
+    pub fn _new(repr: &str) -> Literal {
+        Literal::new(repr.to_string(), lit::classify(repr))
+    }
+
+    pub fn kind(&self) -> LiteralKind {
+        self.kind
+    }
+
     pub fn byte_char(byte: u8) -> Literal {
         match byte {
-            0 => Literal(format!("b'\\0'")),
-            b'\"' => Literal(format!("b'\"'")),
+            0 => Literal::new(format!("b'\\0'"), LiteralKind::Byte),
+            b'\"' => Literal::new(format!("b'\"'"), LiteralKind::Byte),
             n => {
                 let mut escaped = "b'".to_string();
                 escaped.extend(ascii::escape_default(n).map(|c| c as char));
                 escaped.push('\'');
-                Literal(escaped)
+                Literal::new(escaped, LiteralKind::Byte)
             }
         }
     }
@@ -252,19 +1166,24 @@ impl Literal {
             }
         }
         escaped.push('"');
-        Literal(escaped)
+        Literal::new(escaped, LiteralKind::ByteStr)
     }
 
     pub fn doccomment(s: &str) -> Literal {
-        Literal(s.to_string())
+        Literal::new(s.to_string(), LiteralKind::DocComment)
     }
 
     pub fn float(s: f64) -> Literal {
-        Literal(s.to_string())
+        // `1.0` would otherwise print as `1`, which lexes as an integer.
+        let mut text = s.to_string();
+        if !text.contains('.') && !text.contains('e') {
+            text.push_str(".0");
+        }
+        Literal::new(text, LiteralKind::Float)
     }
 
     pub fn integer(s: i64) -> Literal {
-        Literal(s.to_string())
+        Literal::new(s.to_string(), LiteralKind::Int)
     }
 
     pub fn raw_string(s: &str, pounds: usize) -> Literal {
@@ -274,7 +1193,7 @@ impl Literal {
         ret.push_str(s);
         ret.push('"');
         ret.extend((0..pounds).map(|_| "#"));
-        Literal(ret)
+        Literal::new(ret, LiteralKind::RawStr { pounds: pounds })
     }
 
     pub fn raw_byte_string(s: &str, pounds: usize) -> Literal {
@@ -284,13 +1203,13 @@ impl Literal {
         ret.push_str(s);
         ret.push('"');
         ret.extend((0..pounds).map(|_| "#"));
-        Literal(ret)
+        Literal::new(ret, LiteralKind::RawByteStr { pounds: pounds })
     }
 }
 
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.0.fmt(f)
+        self.text.fmt(f)
     }
 }
 
@@ -298,7 +1217,7 @@ macro_rules! ints {
     ($($t:ty,)*) => {$(
         impl From<$t> for Literal {
             fn from(t: $t) -> Literal {
-                Literal(format!(concat!("{}", stringify!($t)), t))
+                Literal::new(format!(concat!("{}", stringify!($t)), t), LiteralKind::Int)
             }
         }
     )*}
@@ -315,7 +1234,7 @@ macro_rules! floats {
             fn from(t: $t) -> Literal {
                 assert!(!t.is_nan());
                 assert!(!t.is_infinite());
-                Literal(format!(concat!("{}", stringify!($t)), t))
+                Literal::new(format!(concat!("{}", stringify!($t)), t), LiteralKind::Float)
             }
         }
     )*}
@@ -330,139 +1249,225 @@ impl<'a> From<&'a str> for Literal {
         let mut s = t.chars().flat_map(|c| c.escape_default()).collect::<String>();
         s.push('"');
         s.insert(0, '"');
-        Literal(s)
+        Literal::new(s, LiteralKind::Str)
     }
 }
 
 impl From<char> for Literal {
     fn from(t: char) -> Literal {
-        Literal(format!("'{}'", t.escape_default().collect::<String>()))
+        Literal::new(format!("'{}'", t.escape_default().collect::<String>()), LiteralKind::Char)
     }
 }
 
-named!(token_stream -> ::TokenStream, map!(
-    many0!(token_tree),
-    |trees| ::TokenStream(TokenStream { inner: trees })
-));
+fn token_stream(mut input: Cursor) -> PResult<::TokenStream> {
+    let mut trees = Vec::new();
+    loop {
+        let input_no_ws = match whitespace(input) {
+            Ok((rest, ())) => rest,
+            Err(e) => {
+                if e.kind == LexErrorKind::UnterminatedComment {
+                    return Err(e);
+                }
+                input
+            }
+        };
+        if input_no_ws.is_empty() || input_no_ws.starts_with(")") ||
+           input_no_ws.starts_with("]") || input_no_ws.starts_with("}") ||
+           input_no_ws.starts_with(NONE_GROUP_CLOSE) {
+            break;
+        }
+        let (a, tt) = token_tree(input_no_ws)?;
+        trees.push(tt);
+        input = a;
+    }
+    Ok((input, ::TokenStream(TokenStream { inner: trees })))
+}
 
-named!(token_tree -> TokenTree,
-       map!(token_kind, |s: TokenNode| {
-           TokenTree {
-               span: ::Span(Span),
-               kind: s,
-           }
-       }));
+fn token_tree(input: Cursor) -> PResult<TokenTree> {
+    let input = skip_whitespace(input);
+    let (rest, kind) = token_kind(input)?;
+    Ok((rest, TokenTree {
+        span: ::Span(Span::new(input.off, rest.off)),
+        kind: kind,
+    }))
+}
 
 named!(token_kind -> TokenNode, alt!(
-    map!(delimited, |(d, s)| TokenNode::Group(d, s))
+    map!(delimited, TokenNode::Group)
+    |
+    map!(literal, TokenNode::Literal) // must be before lifetime
     |
-    map!(literal, TokenNode::Literal) // must be before symbol
+    map!(lifetime, TokenNode::Lifetime)
     |
     map!(symbol, TokenNode::Term)
     |
     map!(op, |(op, kind)| TokenNode::Op(op, kind))
 ));
 
-named!(delimited -> (Delimiter, ::TokenStream), alt!(
-    delimited!(
-        punct!("("),
-        token_stream,
-        punct!(")")
-    ) => { |ts| (Delimiter::Parenthesis, ts) }
-    |
-    delimited!(
-        punct!("["),
-        token_stream,
-        punct!("]")
-    ) => { |ts| (Delimiter::Bracket, ts) }
-    |
-    delimited!(
-        punct!("{"),
-        token_stream,
-        punct!("}")
-    ) => { |ts| (Delimiter::Brace, ts) }
-));
+fn delimited(input: Cursor) -> PResult<::Group> {
+    let input = skip_whitespace(input);
+    let (delim, open, close) = match input.chars().next() {
+        Some('(') => (Delimiter::Parenthesis, "(", ")"),
+        Some('[') => (Delimiter::Bracket, "[", "]"),
+        Some('{') => (Delimiter::Brace, "{", "}"),
+        Some('/') if input.none_groups && input.starts_with(NONE_GROUP_OPEN) => {
+            (Delimiter::None, NONE_GROUP_OPEN, NONE_GROUP_CLOSE)
+        }
+        _ => return Err(LexError::reject(input)),
+    };
+    let (rest, stream) = token_stream(input.advance(open.len()))?;
+    let rest = skip_whitespace(rest);
+    if rest.starts_with(close) {
+        let span = |lo, hi| ::Span(Span::new(lo, hi));
+        let (open_len, close_len) = (open.len() as u32, close.len() as u32);
+        let group = ::Group {
+            delimiter: delim,
+            stream: stream,
+            span_open: span(input.off, input.off + open_len),
+            span_close: span(rest.off, rest.off + close_len),
+        };
+        Ok((rest.advance(close.len()), group))
+    } else if rest.is_empty() {
+        Err(LexError::expected(input, LexErrorKind::UnclosedDelimiter, close))
+    } else {
+        Err(LexError::expected(rest, LexErrorKind::UnbalancedDelimiter, close))
+    }
+}
 
-fn symbol(mut input: &str) -> PResult<::Term> {
-    input = skip_whitespace(input);
+fn symbol(input: Cursor) -> PResult<::Term> {
+    let input = skip_whitespace(input);
 
-    let mut chars = input.char_indices();
+    // `r#` followed by anything other than an identifier is just the term
+    // `r` and a `#`.
+    if input.starts_with("r#") {
+        if let Some(len) = ident_len(input.advance(2)) {
+            let name = &input.rest[2..2 + len];
+            return if !ident::can_be_raw(name) {
+                Err(LexError::new(input, LexErrorKind::InvalidRawIdent))
+            } else if input.edition >= Edition::Edition2021 &&
+                      reserved_prefix(None, input.advance(2 + len)) {
+                Err(LexError::new(input, LexErrorKind::ReservedPrefix))
+            } else {
+                Ok((input.advance(2 + len), ::Term::intern_raw(name)))
+            };
+        }
+    }
+
+    let end = match ident_len(input) {
+        Some(end) => end,
+        None => return Err(LexError::reject(input)),
+    };
+    let name = &input.rest[..end];
+    if input.edition >= Edition::Edition2021 && reserved_prefix(Some(name), input.advance(end)) {
+        // Since 2021 any identifier directly followed by one of these is
+        // reserved for future literal prefixes, like `b` and `r` are today.
+        Err(LexError::new(input, LexErrorKind::ReservedPrefix))
+    } else {
+        Ok((input.advance(end), ::Term::intern(name)))
+    }
+}
 
-    let lifetime = input.starts_with("'");
-    if lifetime {
-        chars.next();
+fn lifetime(input: Cursor) -> PResult<::Lifetime> {
+    let input = skip_whitespace(input);
+    if !input.starts_with("'") {
+        return Err(LexError::reject(input));
     }
 
-    match chars.next() {
-        Some((_, ch)) if UnicodeXID::is_xid_start(ch) || ch == '_' => {}
-        _ => return Err(LexError),
+    let end = match ident_len(input.advance(1)) {
+        Some(len) => len + 1,
+        None => return Err(LexError::new(input, LexErrorKind::InvalidLifetime)),
+    };
+    let a = &input.rest[..end];
+    if a != "'static" && ident::is_keyword(&a[1..], input.edition) {
+        Err(LexError::new(input, LexErrorKind::InvalidLifetime))
+    } else {
+        Ok((input.advance(end), ::Lifetime(::Term::unchecked(a))))
     }
+}
 
-    let mut end = input.len();
+// The length of the identifier at the start of `input`, if there is one.
+fn ident_len(input: Cursor) -> Option<usize> {
+    let mut chars = input.char_indices();
+    match chars.next() {
+        Some((_, ch)) if ident::is_ident_start(ch) => {}
+        _ => return None,
+    }
     for (i, ch) in chars {
-        if !UnicodeXID::is_xid_continue(ch) {
-            end = i;
-            break;
+        if !ident::is_ident_continue(ch) {
+            return Some(i);
         }
     }
+    Some(input.len())
+}
 
-    if lifetime && &input[..end] != "'static" && KEYWORDS.contains(&&input[1..end]) {
-        Err(LexError)
-    } else {
-        Ok((&input[end..], ::Term::intern(&input[..end])))
+// Whether the identifier `name` followed by `after` is a reserved prefix.
+// The prefixes of real literals are exempt where they start one, so that a
+// malformed literal reports its own error; raw identifiers never are.
+fn reserved_prefix(name: Option<&str>, after: Cursor) -> bool {
+    let next = match after.chars().next() {
+        Some(ch @ '#') | Some(ch @ '"') | Some(ch @ '\'') => ch,
+        _ => return false,
+    };
+    match (name, next) {
+        (Some("b"), '"') | (Some("b"), '\'') | (Some("c"), '"') => false,
+        (Some("r"), '"') | (Some("r"), '#') => false,
+        (Some("br"), '"') | (Some("br"), '#') => false,
+        (Some("cr"), '"') | (Some("cr"), '#') => false,
+        _ => true,
     }
 }
 
-// From https://github.com/rust-lang/rust/blob/master/src/libsyntax_pos/symbol.rs
-static KEYWORDS: &'static [&'static str] = &[
-    "abstract", "alignof", "as", "become", "box", "break", "const", "continue",
-    "crate", "do", "else", "enum", "extern", "false", "final", "fn", "for",
-    "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut",
-    "offsetof", "override", "priv", "proc", "pub", "pure", "ref", "return",
-    "self", "Self", "sizeof", "static", "struct", "super", "trait", "true",
-    "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while",
-    "yield",
-];
-
-fn literal(input: &str) -> PResult<::Literal> {
+fn literal(input: Cursor) -> PResult<::Literal> {
     let input_no_ws = skip_whitespace(input);
 
     match literal_nocapture(input_no_ws) {
-        Ok((a, ())) => {
+        Ok((a, kind)) => {
             let start = input.len() - input_no_ws.len();
             let len = input_no_ws.len() - a.len();
             let end = start + len;
-            Ok((a, ::Literal(Literal(input[start..end].to_string()))))
+            let text = input.rest[start..end].to_string();
+            Ok((a, ::Literal(Literal::new(text, kind))))
+        }
+        Err(mut e) => {
+            // Unterminated literals are only noticed at the end of the input,
+            // so point back at where the literal started instead.
+            match e.kind {
+                LexErrorKind::UnterminatedString |
+                LexErrorKind::UnterminatedChar => e.off = input_no_ws.off,
+                _ => {}
+            }
+            Err(e)
         }
-        Err(LexError) => Err(LexError),
     }
 }
 
-named!(literal_nocapture -> (), alt!(
+named!(literal_nocapture -> LiteralKind, alt!(
     string
     |
     byte_string
     |
-    byte
+    c_string
     |
-    character
+    byte => { |_| LiteralKind::Byte }
     |
-    float
+    character => { |_| LiteralKind::Char }
     |
-    int
+    float => { |_| LiteralKind::Float }
     |
-    boolean
+    int => { |_| LiteralKind::Int }
     |
-    doc_comment
+    boolean => { |_| LiteralKind::Bool }
+    |
+    doc_comment => { |_| LiteralKind::DocComment }
 ));
 
-named!(string -> (), alt!(
-    quoted_string
+named!(string -> LiteralKind, alt!(
+    quoted_string => { |_| LiteralKind::Str }
     |
     preceded!(
         punct!("r"),
         raw_string
-    ) => { |_| () }
+    ) => { |pounds| LiteralKind::RawStr { pounds: pounds } }
 ));
 
 named!(quoted_string -> (), delimited!(
@@ -471,39 +1476,32 @@ named!(quoted_string -> (), delimited!(
     tag!("\"")
 ));
 
-fn cooked_string(input: &str) -> PResult<()> {
+fn cooked_string(input: Cursor) -> PResult<()> {
     let mut chars = input.char_indices().peekable();
     while let Some((byte_offset, ch)) = chars.next() {
         match ch {
             '"' => {
-                return Ok((&input[byte_offset..], ()));
+                return Ok((input.advance(byte_offset), ()));
             }
             '\r' => {
                 if let Some((_, '\n')) = chars.next() {
                     // ...
                 } else {
-                    break;
+                    return Err(LexError::new(input.advance(byte_offset),
+                                             LexErrorKind::UnexpectedCharacter));
                 }
             }
             '\\' => {
-                match chars.next() {
-                    Some((_, 'x')) => {
-                        if !backslash_x_char(&mut chars) {
-                            break
-                        }
-                    }
+                let ok = match chars.next() {
+                    Some((_, 'x')) => backslash_x_char(&mut chars),
                     Some((_, 'n')) |
                     Some((_, 'r')) |
                     Some((_, 't')) |
                     Some((_, '\\')) |
                     Some((_, '\'')) |
                     Some((_, '"')) |
-                    Some((_, '0')) => {}
-                    Some((_, 'u')) => {
-                        if !backslash_u(&mut chars) {
-                            break
-                        }
-                    }
+                    Some((_, '0')) => true,
+                    Some((_, 'u')) => backslash_u(&mut chars),
                     Some((_, '\n')) | Some((_, '\r')) => {
                         while let Some(&(_, ch)) = chars.peek() {
                             if ch.is_whitespace() {
@@ -512,80 +1510,110 @@ fn cooked_string(input: &str) -> PResult<()> {
                                 break;
                             }
                         }
+                        true
                     }
-                    _ => break,
+                    _ => false,
+                };
+                if !ok {
+                    return Err(LexError::new(input.advance(byte_offset),
+                                             LexErrorKind::InvalidEscape));
                 }
             }
             _ch => {}
         }
     }
-    Err(LexError)
+    Err(LexError::new(input, LexErrorKind::UnterminatedString))
 }
 
-named!(byte_string -> (), alt!(
+named!(byte_string -> LiteralKind, alt!(
     delimited!(
         punct!("b\""),
         cooked_byte_string,
         tag!("\"")
-    ) => { |_| () }
+    ) => { |_| LiteralKind::ByteStr }
     |
     preceded!(
         punct!("br"),
         raw_string
-    ) => { |_| () }
+    ) => { |pounds| LiteralKind::RawByteStr { pounds: pounds } }
+));
+
+fn c_string(input: Cursor) -> PResult<LiteralKind> {
+    if input.edition < Edition::Edition2021 {
+        return Err(LexError::reject(input));
+    }
+    c_string_literal(input)
+}
+
+named!(c_string_literal -> LiteralKind, alt!(
+    delimited!(
+        punct!("c\""),
+        cooked_string,
+        tag!("\"")
+    ) => { |_| LiteralKind::CStr }
+    |
+    preceded!(
+        punct!("cr"),
+        raw_string
+    ) => { |pounds| LiteralKind::RawCStr { pounds: pounds } }
 ));
 
-fn cooked_byte_string(mut input: &str) -> PResult<()> {
+fn cooked_byte_string(mut input: Cursor) -> PResult<()> {
     let mut bytes = input.bytes().enumerate();
     'outer: while let Some((offset, b)) = bytes.next() {
         match b {
             b'"' => {
-                return Ok((&input[offset..], ()));
+                return Ok((input.advance(offset), ()));
             }
             b'\r' => {
                 if let Some((_, b'\n')) = bytes.next() {
                     // ...
                 } else {
-                    break;
+                    return Err(LexError::new(input.advance(offset),
+                                             LexErrorKind::UnexpectedCharacter));
                 }
             }
             b'\\' => {
-                match bytes.next() {
-                    Some((_, b'x')) => {
-                        if !backslash_x_byte(&mut bytes) {
-                            break
-                        }
-                    }
+                let ok = match bytes.next() {
+                    Some((_, b'x')) => backslash_x_byte(&mut bytes),
                     Some((_, b'n')) |
                     Some((_, b'r')) |
                     Some((_, b't')) |
                     Some((_, b'\\')) |
                     Some((_, b'0')) |
                     Some((_, b'\'')) |
-                    Some((_, b'"'))  => {}
+                    Some((_, b'"'))  => true,
                     Some((newline, b'\n')) |
                     Some((newline, b'\r')) => {
-                        let rest = &input[newline + 1..];
+                        let rest = input.advance(newline + 1);
                         for (offset, ch) in rest.char_indices() {
                             if !ch.is_whitespace() {
-                                input = &rest[offset..];
+                                input = rest.advance(offset);
                                 bytes = input.bytes().enumerate();
                                 continue 'outer;
                             }
                         }
                         break;
                     }
-                    _ => break,
+                    _ => false,
+                };
+                if !ok {
+                    return Err(LexError::new(input.advance(offset),
+                                             LexErrorKind::InvalidEscape));
                 }
             }
             b if b < 0x80 => {}
-            _ => break,
+            _ => {
+                return Err(LexError::new(input.advance(offset),
+                                         LexErrorKind::UnexpectedCharacter));
+            }
         }
     }
-    Err(LexError)
+    Err(LexError::new(input, LexErrorKind::UnterminatedString))
 }
 
-fn raw_string(input: &str) -> PResult<()> {
+// Returns the number of `#` delimiting the string.
+fn raw_string(input: Cursor) -> PResult<usize> {
     let mut chars = input.char_indices();
     let mut n = 0;
     while let Some((byte_offset, ch)) = chars.next() {
@@ -595,20 +1623,20 @@ fn raw_string(input: &str) -> PResult<()> {
                 break;
             }
             '#' => {}
-            _ => return Err(LexError),
+            _ => return Err(LexError::reject(input)),
         }
     }
     for (byte_offset, ch) in chars {
         match ch {
-            '"' if input[byte_offset + 1..].starts_with(&input[..n]) => {
-                let rest = &input[byte_offset + 1 + n..];
-                return Ok((rest, ()))
+            '"' if input.advance(byte_offset + 1).starts_with(&input.rest[..n]) => {
+                let rest = input.advance(byte_offset + 1 + n);
+                return Ok((rest, n))
             }
             '\r' => {}
             _ => {}
         }
     }
-    Err(LexError)
+    Err(LexError::new(input, LexErrorKind::UnterminatedString))
 }
 
 named!(byte -> (), do_parse!(
@@ -619,11 +1647,11 @@ named!(byte -> (), do_parse!(
     (())
 ));
 
-fn cooked_byte(input: &str) -> PResult<()> {
+fn cooked_byte(input: Cursor) -> PResult<()> {
     let mut bytes = input.bytes().enumerate();
     let ok = match bytes.next().map(|(_, b)| b) {
         Some(b'\\') => {
-            match bytes.next().map(|(_, b)| b) {
+            let ok = match bytes.next().map(|(_, b)| b) {
                 Some(b'x') => backslash_x_byte(&mut bytes),
                 Some(b'n') |
                 Some(b'r') |
@@ -633,17 +1661,21 @@ fn cooked_byte(input: &str) -> PResult<()> {
                 Some(b'\'') |
                 Some(b'"') => true,
                 _ => false,
+            };
+            if !ok {
+                return Err(LexError::new(input, LexErrorKind::InvalidEscape));
             }
+            true
         }
         b => b.is_some(),
     };
     if ok {
         match bytes.next() {
-            Some((offset, _)) => Ok((&input[offset..], ())),
-            None => Ok(("", ())),
+            Some((offset, _)) => Ok((input.advance(offset), ())),
+            None => Ok((input.advance(input.len()), ())),
         }
     } else {
-        Err(LexError)
+        Err(LexError::new(input, LexErrorKind::UnterminatedChar))
     }
 }
 
@@ -654,11 +1686,11 @@ named!(character -> (), do_parse!(
     (())
 ));
 
-fn cooked_char(input: &str) -> PResult<()> {
+fn cooked_char(input: Cursor) -> PResult<()> {
     let mut chars = input.char_indices();
     let ok = match chars.next().map(|(_, ch)| ch) {
         Some('\\') => {
-            match chars.next().map(|(_, ch)| ch) {
+            let ok = match chars.next().map(|(_, ch)| ch) {
                 Some('x') => backslash_x_char(&mut chars),
                 Some('u') => backslash_u(&mut chars),
                 Some('n') |
@@ -669,14 +1701,21 @@ fn cooked_char(input: &str) -> PResult<()> {
                 Some('\'') |
                 Some('"') => true,
                 _ => false,
+            };
+            if !ok {
+                return Err(LexError::new(input, LexErrorKind::InvalidEscape));
             }
+            true
         }
         ch => ch.is_some(),
     };
     if ok {
-        Ok((chars.as_str(), ()))
+        match chars.next() {
+            Some((idx, _)) => Ok((input.advance(idx), ())),
+            None => Ok((input.advance(input.len()), ())),
+        }
     } else {
-        Err(LexError)
+        Err(LexError::reject(input))
     }
 }
 
@@ -737,21 +1776,30 @@ fn backslash_u<I>(chars: &mut I) -> bool
     true
 }
 
-fn float(input: &str) -> PResult<()> {
+fn float(input: Cursor) -> PResult<()> {
     let (rest, ()) = float_digits(input)?;
     for suffix in &["f32", "f64"] {
         if rest.starts_with(suffix) {
-            return word_break(&rest[suffix.len()..]);
+            return suffix_break(rest, rest.advance(suffix.len()));
         }
     }
-    word_break(rest)
+    suffix_break(rest, rest)
+}
+
+/// Requires that a number literal ends at `after_suffix`, reporting an
+/// invalid suffix starting at `suffix` otherwise.
+fn suffix_break<'a>(suffix: Cursor<'a>, after_suffix: Cursor<'a>) -> PResult<'a, ()> {
+    match word_break(after_suffix) {
+        Ok(res) => Ok(res),
+        Err(_) => Err(LexError::new(suffix, LexErrorKind::InvalidSuffix)),
+    }
 }
 
-fn float_digits(input: &str) -> PResult<()> {
+fn float_digits(input: Cursor) -> PResult<()> {
     let mut chars = input.chars().peekable();
     match chars.next() {
         Some(ch) if ch >= '0' && ch <= '9' => {}
-        _ => return Err(LexError),
+        _ => return Err(LexError::reject(input)),
     }
 
     let mut len = 1;
@@ -771,7 +1819,7 @@ fn float_digits(input: &str) -> PResult<()> {
                 if chars.peek()
                        .map(|&ch| ch == '.' || UnicodeXID::is_xid_start(ch))
                        .unwrap_or(false) {
-                    return Err(LexError);
+                    return Err(LexError::reject(input));
                 }
                 len += 1;
                 has_dot = true;
@@ -786,9 +1834,9 @@ fn float_digits(input: &str) -> PResult<()> {
         }
     }
 
-    let rest = &input[len..];
+    let rest = input.advance(len);
     if !(has_dot || has_exp || rest.starts_with("f32") || rest.starts_with("f64")) {
-        return Err(LexError);
+        return Err(LexError::reject(input));
     }
 
     if has_exp {
@@ -815,14 +1863,14 @@ fn float_digits(input: &str) -> PResult<()> {
             }
         }
         if !has_exp_value {
-            return Err(LexError);
+            return Err(LexError::new(input.advance(len), LexErrorKind::InvalidNumber));
         }
     }
 
-    Ok((&input[len..], ()))
+    Ok((input.advance(len), ()))
 }
 
-fn int(input: &str) -> PResult<()> {
+fn int(input: Cursor) -> PResult<()> {
     let (rest, ()) = digits(input)?;
     for suffix in &[
         "isize",
@@ -839,21 +1887,21 @@ fn int(input: &str) -> PResult<()> {
         "u128",
     ] {
         if rest.starts_with(suffix) {
-            return word_break(&rest[suffix.len()..]);
+            return suffix_break(rest, rest.advance(suffix.len()));
         }
     }
-    word_break(rest)
+    suffix_break(rest, rest)
 }
 
-fn digits(mut input: &str) -> PResult<()> {
+fn digits(mut input: Cursor) -> PResult<()> {
     let base = if input.starts_with("0x") {
-        input = &input[2..];
+        input = input.advance(2);
         16
     } else if input.starts_with("0o") {
-        input = &input[2..];
+        input = input.advance(2);
         8
     } else if input.starts_with("0b") {
-        input = &input[2..];
+        input = input.advance(2);
         2
     } else {
         10
@@ -864,11 +1912,11 @@ fn digits(mut input: &str) -> PResult<()> {
     for b in input.bytes() {
         let digit = match b {
             b'0'...b'9' => (b - b'0') as u64,
-            b'a'...b'f' => 10 + (b - b'a') as u64,
-            b'A'...b'F' => 10 + (b - b'A') as u64,
+            b'a'...b'f' if base == 16 => 10 + (b - b'a') as u64,
+            b'A'...b'F' if base == 16 => 10 + (b - b'A') as u64,
             b'_' => {
                 if empty && base == 10 {
-                    return Err(LexError);
+                    return Err(LexError::reject(input));
                 }
                 len += 1;
                 continue;
@@ -876,15 +1924,17 @@ fn digits(mut input: &str) -> PResult<()> {
             _ => break,
         };
         if digit >= base {
-            return Err(LexError);
+            return Err(LexError::new(input.advance(len), LexErrorKind::InvalidNumber));
         }
         len += 1;
         empty = false;
     }
-    if empty {
-        Err(LexError)
+    if empty && base == 10 {
+        Err(LexError::reject(input))
+    } else if empty {
+        Err(LexError::new(input, LexErrorKind::InvalidNumber))
     } else {
-        Ok((&input[len..], ()))
+        Ok((input.advance(len), ()))
     }
 }
 
@@ -894,33 +1944,33 @@ named!(boolean -> (), alt!(
     keyword!("false") => { |_| () }
 ));
 
-fn op(input: &str) -> PResult<(char, Spacing)> {
+fn op(input: Cursor) -> PResult<(char, Spacing)> {
     let input = skip_whitespace(input);
     match op_char(input) {
         Ok((rest, ch)) => {
             let kind = match op_char(rest) {
                 Ok(_) => Spacing::Joint,
-                Err(LexError) => Spacing::Alone,
+                Err(_) => Spacing::Alone,
             };
             Ok((rest, (ch, kind)))
         }
-        Err(LexError) => Err(LexError),
+        Err(e) => Err(e),
     }
 }
 
-fn op_char(input: &str) -> PResult<char> {
+fn op_char(input: Cursor) -> PResult<char> {
     let mut chars = input.chars();
     let first = match chars.next() {
         Some(ch) => ch,
         None => {
-            return Err(LexError);
+            return Err(LexError::reject(input));
         }
     };
     let recognized = "~!@#$%^&*-=+|;:,<.>/?";
     if recognized.contains(first) {
-        Ok((chars.as_str(), first))
+        Ok((input.advance(first.len_utf8()), first))
     } else {
-        Err(LexError)
+        Err(LexError::reject(input))
     }
 }
 
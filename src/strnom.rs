@@ -1,20 +1,83 @@
 //! Adapted from [`nom`](https://github.com/Geal/nom).
 
+use std::str::{Bytes, CharIndices, Chars};
+
 use unicode_xid::UnicodeXID;
 
+use {Edition, LexErrorKind};
 use imp::LexError;
 
-pub type PResult<'a, O> = Result<(&'a str, O), LexError>;
+/// The remaining input along with its byte offset into the source map, so that
+/// every parser can tell where the tokens it produces came from, the edition
+/// whose lexical rules apply, and whether the `{:#}` markers for invisible
+/// groups are read as groups rather than skipped as comments.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct Cursor<'a> {
+    pub rest: &'a str,
+    pub off: u32,
+    pub edition: Edition,
+    pub none_groups: bool,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn advance(&self, amt: usize) -> Cursor<'a> {
+        Cursor {
+            rest: &self.rest[amt..],
+            off: self.off + (amt as u32),
+            edition: self.edition,
+            none_groups: self.none_groups,
+        }
+    }
+
+    pub fn find(&self, p: char) -> Option<usize> {
+        self.rest.find(p)
+    }
+
+    pub fn starts_with(&self, s: &str) -> bool {
+        self.rest.starts_with(s)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rest.len()
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.rest.as_bytes()
+    }
 
-pub fn whitespace(input: &str) -> PResult<()> {
+    pub fn bytes(&self) -> Bytes<'a> {
+        self.rest.bytes()
+    }
+
+    pub fn chars(&self) -> Chars<'a> {
+        self.rest.chars()
+    }
+
+    pub fn char_indices(&self) -> CharIndices<'a> {
+        self.rest.char_indices()
+    }
+}
+
+pub type PResult<'a, O> = Result<(Cursor<'a>, O), LexError>;
+
+/// The comments which `{:#}` prints around a group with no delimiters, and
+/// which `TokenStream::parse_alternate` reads back as one instead of skipping.
+pub const NONE_GROUP_OPEN: &'static str = "/*«*/";
+pub const NONE_GROUP_CLOSE: &'static str = "/*»*/";
+
+pub fn whitespace(input: Cursor) -> PResult<()> {
     if input.is_empty() {
-        return Err(LexError);
+        return Err(LexError::reject(input));
     }
 
     let bytes = input.as_bytes();
     let mut i = 0;
     while i < bytes.len() {
-        let s = &input[i..];
+        let s = input.advance(i);
         if bytes[i] == b'/' {
             if s.starts_with("//") && (!s.starts_with("///") || s.starts_with("////")) &&
                !s.starts_with("//!") {
@@ -24,7 +87,9 @@ pub fn whitespace(input: &str) -> PResult<()> {
                 }
                 break;
             } else if s.starts_with("/*") && (!s.starts_with("/**") || s.starts_with("/***")) &&
-                      !s.starts_with("/*!") {
+                      !s.starts_with("/*!") &&
+                      !(s.none_groups && (s.starts_with(NONE_GROUP_OPEN) ||
+                                          s.starts_with(NONE_GROUP_CLOSE))) {
                 let (_, com) = block_comment(s)?;
                 i += com.len();
                 continue;
@@ -47,15 +112,15 @@ pub fn whitespace(input: &str) -> PResult<()> {
         return if i > 0 {
             Ok((s, ()))
         } else {
-            Err(LexError)
+            Err(LexError::reject(s))
         };
     }
-    Ok(("", ()))
+    Ok((input.advance(input.len()), ()))
 }
 
-pub fn block_comment(input: &str) -> PResult<&str> {
+pub fn block_comment(input: Cursor) -> PResult<&str> {
     if !input.starts_with("/*") {
-        return Err(LexError);
+        return Err(LexError::reject(input));
     }
 
     let mut depth = 0;
@@ -69,19 +134,19 @@ pub fn block_comment(input: &str) -> PResult<&str> {
         } else if bytes[i] == b'*' && bytes[i + 1] == b'/' {
             depth -= 1;
             if depth == 0 {
-                return Ok((&input[i + 2..], &input[..i + 2]));
+                return Ok((input.advance(i + 2), &input.rest[..i + 2]));
             }
             i += 1; // eat '/'
         }
         i += 1;
     }
-    Err(LexError)
+    Err(LexError::new(input, LexErrorKind::UnterminatedComment))
 }
 
-pub fn skip_whitespace(input: &str) -> &str {
+pub fn skip_whitespace(input: Cursor) -> Cursor {
     match whitespace(input) {
         Ok((rest, _)) => rest,
-        Err(LexError) => input,
+        Err(_) => input,
     }
 }
 
@@ -90,16 +155,16 @@ fn is_whitespace(ch: char) -> bool {
     ch.is_whitespace() || ch == '\u{200e}' || ch == '\u{200f}'
 }
 
-pub fn word_break(input: &str) -> PResult<()> {
+pub fn word_break(input: Cursor) -> PResult<()> {
     match input.chars().next() {
-        Some(ch) if UnicodeXID::is_xid_continue(ch) => Err(LexError),
+        Some(ch) if UnicodeXID::is_xid_continue(ch) => Err(LexError::reject(input)),
         Some(_) | None => Ok((input, ())),
     }
 }
 
 macro_rules! named {
     ($name:ident -> $o:ty, $submac:ident!( $($args:tt)* )) => {
-        fn $name(i: &str) -> $crate::strnom::PResult<$o> {
+        fn $name<'a>(i: Cursor<'a>) -> $crate::strnom::PResult<'a, $o> {
             $submac!(i, $($args)*)
         }
     };
@@ -113,14 +178,20 @@ macro_rules! alt {
     ($i:expr, $subrule:ident!( $($args:tt)*) | $($rest:tt)*) => {
         match $subrule!($i, $($args)*) {
             res @ Ok(_) => res,
-            _ => alt!($i, $($rest)*)
+            Err(e) => match alt!($i, $($rest)*) {
+                Err(e2) => Err(e.or(e2)),
+                res => res,
+            }
         }
     };
 
     ($i:expr, $subrule:ident!( $($args:tt)* ) => { $gen:expr } | $($rest:tt)+) => {
         match $subrule!($i, $($args)*) {
             Ok((i, o)) => Ok((i, $gen(o))),
-            Err(LexError) => alt!($i, $($rest)*)
+            Err(e) => match alt!($i, $($rest)*) {
+                Err(e2) => Err(e.or(e2)),
+                res => res,
+            }
         }
     };
 
@@ -135,7 +206,7 @@ macro_rules! alt {
     ($i:expr, $subrule:ident!( $($args:tt)* ) => { $gen:expr }) => {
         match $subrule!($i, $($args)*) {
             Ok((i, o)) => Ok((i, $gen(o))),
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
         }
     };
 
@@ -159,7 +230,7 @@ macro_rules! do_parse {
 
     ($i:expr, $submac:ident!( $($args:tt)* ) >> $($rest:tt)*) => {
         match $submac!($i, $($args)*) {
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
             Ok((i, _)) => do_parse!(i, $($rest)*),
         }
     };
@@ -170,7 +241,7 @@ macro_rules! do_parse {
 
     ($i:expr, $field:ident : $submac:ident!( $($args:tt)* ) >> $($rest:tt)*) => {
         match $submac!($i, $($args)*) {
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
             Ok((i, o)) => {
                 let $field = o;
                 do_parse!(i, $($rest)*)
@@ -183,7 +254,7 @@ macro_rules! peek {
     ($i:expr, $submac:ident!( $($args:tt)* )) => {
         match $submac!($i, $($args)*) {
             Ok((_, o)) => Ok(($i, o)),
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
         }
     };
 }
@@ -198,7 +269,7 @@ macro_rules! option {
     ($i:expr, $f:expr) => {
         match $f($i) {
             Ok((i, o)) => Ok((i, Some(o))),
-            Err(LexError) => Ok(($i, None)),
+            Err(_) => Ok(($i, None)),
         }
     };
 }
@@ -206,7 +277,7 @@ macro_rules! option {
 macro_rules! take_until {
     ($i:expr, $substr:expr) => {{
         if $substr.len() > $i.len() {
-            Err(LexError)
+            Err(LexError::reject($i))
         } else {
             let substr_vec: Vec<char> = $substr.chars().collect();
             let mut window: Vec<char> = vec![];
@@ -228,9 +299,9 @@ macro_rules! take_until {
                 }
             }
             if parsed {
-                Ok((&$i[offset..], &$i[..offset]))
+                Ok(($i.advance(offset), &$i.rest[..offset]))
             } else {
-                Err(LexError)
+                Err(LexError::reject($i))
             }
         }
     }};
@@ -250,14 +321,14 @@ macro_rules! tuple_parser {
 
     ($i:expr, (), $submac:ident!( $($args:tt)* ), $($rest:tt)*) => {
         match $submac!($i, $($args)*) {
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
             Ok((i, o)) => tuple_parser!(i, (o), $($rest)*),
         }
     };
 
     ($i:expr, ($($parsed:tt)*), $submac:ident!( $($args:tt)* ), $($rest:tt)*) => {
         match $submac!($i, $($args)*) {
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
             Ok((i, o)) => tuple_parser!(i, ($($parsed)* , o), $($rest)*),
         }
     };
@@ -272,7 +343,7 @@ macro_rules! tuple_parser {
 
     ($i:expr, ($($parsed:expr),*), $submac:ident!( $($args:tt)* )) => {
         match $submac!($i, $($args)*) {
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
             Ok((i, o)) => Ok((i, ($($parsed),*, o)))
         }
     };
@@ -285,8 +356,8 @@ macro_rules! tuple_parser {
 macro_rules! not {
     ($i:expr, $submac:ident!( $($args:tt)* )) => {
         match $submac!($i, $($args)*) {
-            Ok((_, _)) => Err(LexError),
-            Err(LexError) => Ok(($i, ())),
+            Ok((_, _)) => Err(LexError::reject($i)),
+            Err(_) => Ok(($i, ())),
         }
     };
 }
@@ -294,9 +365,9 @@ macro_rules! not {
 macro_rules! tag {
     ($i:expr, $tag:expr) => {
         if $i.starts_with($tag) {
-            Ok((&$i[$tag.len()..], &$i[..$tag.len()]))
+            Ok(($i.advance($tag.len()), &$i.rest[..$tag.len()]))
         } else {
-            Err(LexError)
+            Err(LexError::reject($i))
         }
     };
 }
@@ -308,12 +379,12 @@ macro_rules! punct {
 }
 
 /// Do not use directly. Use `punct!`.
-pub fn punct<'a>(input: &'a str, token: &'static str) -> PResult<'a, &'a str> {
+pub fn punct<'a>(input: Cursor<'a>, token: &'static str) -> PResult<'a, &'a str> {
     let input = skip_whitespace(input);
     if input.starts_with(token) {
-        Ok((&input[token.len()..], token))
+        Ok((input.advance(token.len()), token))
     } else {
-        Err(LexError)
+        Err(LexError::reject(input))
     }
 }
 
@@ -324,15 +395,15 @@ macro_rules! keyword {
 }
 
 /// Do not use directly. Use `keyword!`.
-pub fn keyword<'a>(input: &'a str, token: &'static str) -> PResult<'a, &'a str> {
+pub fn keyword<'a>(input: Cursor<'a>, token: &'static str) -> PResult<'a, &'a str> {
     match punct(input, token) {
         Ok((rest, _)) => {
             match word_break(rest) {
                 Ok((_, _)) => Ok((rest, token)),
-                Err(LexError) => Err(LexError),
+                Err(e) => Err(e),
             }
         }
-        Err(LexError) => Err(LexError),
+        Err(e) => Err(e),
     }
 }
 
@@ -340,7 +411,7 @@ macro_rules! preceded {
     ($i:expr, $submac:ident!( $($args:tt)* ), $submac2:ident!( $($args2:tt)* )) => {
         match tuple!($i, $submac!($($args)*), $submac2!($($args2)*)) {
             Ok((remaining, (_, o))) => Ok((remaining, o)),
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
         }
     };
 
@@ -352,7 +423,7 @@ macro_rules! preceded {
 macro_rules! delimited {
     ($i:expr, $submac:ident!( $($args:tt)* ), $($rest:tt)+) => {
         match tuple_parser!($i, (), $submac!($($args)*), $($rest)*) {
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
             Ok((i1, (_, o, _))) => Ok((i1, o))
         }
     };
@@ -361,7 +432,7 @@ macro_rules! delimited {
 macro_rules! map {
     ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => {
         match $submac!($i, $($args)*) {
-            Err(LexError) => Err(LexError),
+            Err(e) => Err(e),
             Ok((i, o)) => Ok((i, call!(o, $g)))
         }
     };
@@ -370,37 +441,3 @@ macro_rules! map {
         map!($i, call!($f), $g)
     };
 }
-
-macro_rules! many0 {
-    ($i:expr, $f:expr) => {{
-        let ret;
-        let mut res   = ::std::vec::Vec::new();
-        let mut input = $i;
-
-        loop {
-            if input.is_empty() {
-                ret = Ok((input, res));
-                break;
-            }
-
-            match $f(input) {
-                Err(LexError) => {
-                    ret = Ok((input, res));
-                    break;
-                }
-                Ok((i, o)) => {
-                    // loop trip must always consume (otherwise infinite loops)
-                    if i.len() == input.len() {
-                        ret = Err(LexError);
-                        break;
-                    }
-
-                    res.push(o);
-                    input = i;
-                }
-            }
-        }
-
-        ret
-    }};
-}
@@ -1,18 +1,40 @@
 use std::ascii;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::iter;
+use std::mem;
 use std::ops;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use proc_macro;
 
-use {TokenTree, TokenNode, Delimiter, Spacing};
+use {TokenTree, TokenNode, Delimiter, Spacing, LineColumn, LexErrorKind, Level, LiteralKind, Edition};
+use lit;
 
 #[derive(Clone)]
 pub struct TokenStream(proc_macro::TokenStream);
 
 pub struct LexError(proc_macro::LexError);
 
+impl LexError {
+    pub fn kind(&self) -> LexErrorKind {
+        LexErrorKind::Other
+    }
+
+    pub fn expected_token(&self) -> Option<&str> {
+        None
+    }
+
+    pub fn span(&self) -> Span {
+        Span::call_site()
+    }
+
+    pub fn offset(&self) -> usize {
+        0
+    }
+}
+
 impl TokenStream {
     pub fn empty() -> TokenStream {
         TokenStream(proc_macro::TokenStream::empty())
@@ -21,6 +43,23 @@ impl TokenStream {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    // The compiler lexes with the edition of the crate being compiled.
+    pub fn parse_with_edition(src: &str, _edition: Edition) -> Result<TokenStream, LexError> {
+        src.parse()
+    }
+
+    // The compiler skips the markers like any other comment, so the groups
+    // they mark are flattened.
+    pub fn parse_alternate(src: &str) -> Result<TokenStream, LexError> {
+        src.parse()
+    }
+
+    // The compiler has no way to register a file of our own, so the path is
+    // dropped and the tokens get call-site spans.
+    pub fn parse_file(_path: &Path, src: &str, _edition: Edition) -> Result<TokenStream, LexError> {
+        src.parse()
+    }
 }
 
 impl FromStr for TokenStream {
@@ -54,14 +93,14 @@ impl From<TokenTree> for TokenStream {
         TokenStream(proc_macro::TokenTree {
             span: (tree.span.0).0,
             kind: match tree.kind {
-                TokenNode::Group(delim, s) => {
-                    let delim = match delim {
+                TokenNode::Group(group) => {
+                    let delim = match group.delimiter {
                         Delimiter::Parenthesis => proc_macro::Delimiter::Parenthesis,
                         Delimiter::Bracket => proc_macro::Delimiter::Bracket,
                         Delimiter::Brace => proc_macro::Delimiter::Brace,
                         Delimiter::None => proc_macro::Delimiter::None,
                     };
-                    proc_macro::TokenNode::Group(delim, (s.0).0)
+                    proc_macro::TokenNode::Group(delim, (group.stream.0).0)
                 }
                 TokenNode::Op(ch, kind) => {
                     let kind = match kind {
@@ -73,6 +112,11 @@ impl From<TokenTree> for TokenStream {
                 TokenNode::Term(s) => {
                     proc_macro::TokenNode::Term((s.0).0)
                 }
+                // The compiler has no separate lifetime token, just a term
+                // starting with `'`.
+                TokenNode::Lifetime(l) => {
+                    proc_macro::TokenNode::Term(((l.0).0).0)
+                }
                 TokenNode::Literal(l) => {
                     proc_macro::TokenNode::Literal((l.0).0)
                 }
@@ -88,6 +132,38 @@ impl iter::FromIterator<TokenStream> for TokenStream {
     }
 }
 
+impl iter::Extend<TokenTree> for TokenStream {
+    fn extend<I: IntoIterator<Item=TokenTree>>(&mut self, trees: I) {
+        self.extend(trees.into_iter().map(TokenStream::from));
+    }
+}
+
+impl iter::Extend<TokenStream> for TokenStream {
+    fn extend<I: IntoIterator<Item=TokenStream>>(&mut self, streams: I) {
+        let prev = mem::replace(&mut self.0, proc_macro::TokenStream::empty());
+        let streams = streams.into_iter().map(|s| s.0);
+        self.0 = iter::once(prev).chain(streams).collect();
+    }
+}
+
+// `proc_macro::TokenStream` has no notion of equality, so compare the trees
+// one at a time, which also gets us the span-insensitive behavior.
+impl PartialEq for TokenStream {
+    fn eq(&self, other: &TokenStream) -> bool {
+        self.clone().into_iter().eq(other.clone().into_iter())
+    }
+}
+
+impl Eq for TokenStream {}
+
+impl Hash for TokenStream {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for tree in self.clone() {
+            tree.hash(state);
+        }
+    }
+}
+
 impl fmt::Debug for TokenStream {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt(f)
@@ -100,6 +176,12 @@ impl fmt::Debug for LexError {
     }
 }
 
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("cannot parse string into token stream")
+    }
+}
+
 pub struct TokenTreeIter(proc_macro::TokenTreeIter);
 
 impl IntoIterator for TokenStream {
@@ -119,8 +201,9 @@ impl Iterator for TokenTreeIter {
             Some(n) => n,
             None => return None,
         };
+        let span = ::Span(Span(token.span));
         Some(TokenTree {
-            span: ::Span(Span(token.span)),
+            span: span,
             kind: match token.kind {
                 proc_macro::TokenNode::Group(delim, s) => {
                     let delim = match delim {
@@ -129,7 +212,10 @@ impl Iterator for TokenTreeIter {
                         proc_macro::Delimiter::Brace => Delimiter::Brace,
                         proc_macro::Delimiter::None => Delimiter::None,
                     };
-                    TokenNode::Group(delim, ::TokenStream(TokenStream(s)))
+                    // The compiler only tracks the span of the whole group.
+                    let mut group = ::Group::new(delim, ::TokenStream(TokenStream(s)));
+                    group.set_span(span);
+                    TokenNode::Group(group)
                 }
                 proc_macro::TokenNode::Op(ch, kind) => {
                     let kind = match kind {
@@ -139,7 +225,11 @@ impl Iterator for TokenTreeIter {
                     TokenNode::Op(ch, kind)
                 }
                 proc_macro::TokenNode::Term(s) => {
-                    TokenNode::Term(::Term(Term(s)))
+                    if s.as_str().starts_with('\'') {
+                        TokenNode::Lifetime(::Lifetime(::Term(Term(s))))
+                    } else {
+                        TokenNode::Term(::Term(Term(s)))
+                    }
                 }
                 proc_macro::TokenNode::Literal(l) => {
                     TokenNode::Literal(::Literal(Literal(l)))
@@ -159,13 +249,112 @@ impl fmt::Debug for TokenTreeIter {
     }
 }
 
-#[derive(Copy, Clone, Default)]
+// Symbols belong to the compiler, so there's nothing to free or count.
+pub fn with_interner<F, R>(f: F) -> R
+    where F: FnOnce() -> R
+{
+    f()
+}
+
+pub fn interner_stats() -> Option<::interner::Stats> {
+    None
+}
+
+// The compiler applies hygiene to def_site spans itself.
+pub fn resolve_hygiene(stream: ::TokenStream) -> ::TokenStream {
+    stream
+}
+
+pub fn emit_diagnostic(diag: ::Diagnostic) -> ::TokenStream {
+    fn level(level: Level) -> proc_macro::Level {
+        match level {
+            Level::Error => proc_macro::Level::Error,
+            Level::Warning => proc_macro::Level::Warning,
+            Level::Note => proc_macro::Level::Note,
+            Level::Help => proc_macro::Level::Help,
+        }
+    }
+
+    let mut inner = proc_macro::Diagnostic::spanned((diag.span().0).0,
+                                                    level(diag.level()),
+                                                    diag.message());
+    for child in diag.children() {
+        let span = (child.span().0).0;
+        inner = match child.level() {
+            Level::Error => inner.span_error(span, child.message()),
+            Level::Warning => inner.span_warning(span, child.message()),
+            Level::Note => inner.span_note(span, child.message()),
+            Level::Help => inner.span_help(span, child.message()),
+        };
+    }
+    inner.emit();
+    ::TokenStream::empty()
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SourceFile(proc_macro::SourceFile);
+
+impl SourceFile {
+    pub fn path(&self) -> PathBuf {
+        self.0.path()
+    }
+
+    pub fn is_real(&self) -> bool {
+        self.0.is_real()
+    }
+}
+
+impl fmt::Debug for SourceFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
 pub struct Span(proc_macro::Span);
 
 impl Span {
     pub fn call_site() -> Span {
         Span(proc_macro::Span::call_site())
     }
+
+    pub fn start(&self) -> LineColumn {
+        let proc_macro::LineColumn { line, column } = self.0.start();
+        LineColumn { line: line, column: column }
+    }
+
+    pub fn end(&self) -> LineColumn {
+        let proc_macro::LineColumn { line, column } = self.0.end();
+        LineColumn { line: line, column: column }
+    }
+
+    pub fn source_text(&self) -> Option<String> {
+        self.0.source_text()
+    }
+
+    pub fn source_file(&self) -> SourceFile {
+        SourceFile(self.0.source_file())
+    }
+
+    pub fn def_site() -> Span {
+        Span(proc_macro::Span::def_site())
+    }
+
+    pub fn mixed_site() -> Span {
+        Span(proc_macro::Span::mixed_site())
+    }
+
+    pub fn resolved_at(&self, other: Span) -> Span {
+        Span(self.0.resolved_at(other.0))
+    }
+
+    pub fn located_at(&self, other: Span) -> Span {
+        Span(self.0.located_at(other.0))
+    }
+
+    pub fn join(&self, other: Span) -> Option<Span> {
+        self.0.join(other.0).map(Span)
+    }
 }
 
 impl fmt::Debug for Span {
@@ -174,9 +363,21 @@ impl fmt::Debug for Span {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Term(proc_macro::Term);
 
+// The compiler's symbols have no separate flag for rawness, so a raw term is
+// interned along with its `r#` prefix.
+impl Term {
+    pub fn raw(string: &str) -> Term {
+        Term(proc_macro::Term::intern(&format!("r#{}", string)))
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.0.as_str().starts_with("r#")
+    }
+}
+
 impl<'a> From<&'a str> for Term {
     fn from(string: &'a str) -> Term {
         Term(proc_macro::Term::intern(string))
@@ -187,7 +388,12 @@ impl ops::Deref for Term {
     type Target = str;
 
     fn deref(&self) -> &str {
-        self.0.as_str()
+        let s = self.0.as_str();
+        if self.is_raw() {
+            &s[2..]
+        } else {
+            s
+        }
     }
 }
 
@@ -201,6 +407,14 @@ impl fmt::Debug for Term {
 pub struct Literal(proc_macro::Literal);
 
 impl Literal {
+    pub fn _new(repr: &str) -> Literal {
+        Literal(to_literal(repr))
+    }
+
+    pub fn kind(&self) -> LiteralKind {
+        lit::classify(&self.0.to_string())
+    }
+
     pub fn byte_char(byte: u8) -> Literal {
         match byte {
             0 => Literal(to_literal("b'\\0'")),
@@ -257,6 +471,20 @@ impl fmt::Display for Literal {
     }
 }
 
+impl PartialEq for Literal {
+    fn eq(&self, other: &Literal) -> bool {
+        self.0.to_string() == other.0.to_string()
+    }
+}
+
+impl Eq for Literal {}
+
+impl Hash for Literal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_string().hash(state)
+    }
+}
+
 impl fmt::Debug for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt(f)
@@ -0,0 +1,9 @@
+extern crate proc_macro2;
+
+use proc_macro2::Span;
+
+fn assert_send<T: Send>() {}
+
+fn main() {
+    assert_send::<Span>(); //~ the trait bound `*const (): std::marker::Send` is not satisfied in `proc_macro2::Span`
+}
@@ -8,7 +8,10 @@ fn run_mode(mode: &'static str) {
     compiletest::run_tests(&config);
 }
 
+// `Term` and `Span` are only `!Send` and `!Sync` without the `thread-safe`
+// feature.
 #[test]
+#[cfg(not(feature = "thread-safe"))]
 fn compile_fail() {
     run_mode("compile-fail");
 }
@@ -1,6 +1,12 @@
+#[macro_use]
 extern crate proc_macro2;
 
-use proc_macro2::{Term, Literal, TokenStream};
+use proc_macro2::{Term, Literal, LiteralKind, TokenStream, TokenNode, TokenTree, LexErrorKind};
+use proc_macro2::{Edition, KeywordClass, Lifetime, Group, Span};
+use proc_macro2::{Diagnostic, Errors, Level, ToTokens, Delimiter, Spacing, LitErrorKind};
+use proc_macro2::buffer::TokenBuffer;
+use proc_macro2::{hygiene, interner, op};
+use proc_macro2::parse::{self, Parse, ParseStream, Punctuated};
 
 #[test]
 fn symbols() {
@@ -18,10 +24,13 @@ fn literals() {
 fn roundtrip() {
     fn roundtrip(p: &str) {
         println!("parse: {}", p);
-        let s = p.parse::<TokenStream>().unwrap().to_string();
+        let stream = p.parse::<TokenStream>().unwrap();
+        let s = stream.to_string();
         println!("first: {}", s);
-        let s2 = s.to_string().parse::<TokenStream>().unwrap().to_string();
-        assert_eq!(s, s2);
+        let reparsed = s.parse::<TokenStream>().unwrap();
+        assert_eq!(reparsed, stream);
+        assert_eq!(s, reparsed.to_string());
+        assert_eq!(TokenStream::parse_alternate(&format!("{:#}", stream)).unwrap(), stream);
     }
     roundtrip("a");
     roundtrip("<<");
@@ -47,6 +56,65 @@ fn roundtrip() {
     ");
     roundtrip("'a");
     roundtrip("'static");
+    roundtrip("a <<= b ..= c :: d -> e => f");
+    roundtrip("x /= y / / z /* c */ / * w");
+    roundtrip("{ } ( [ ] ) r#fn 'a: 'b");
+}
+
+#[test]
+fn roundtrip_none_groups() {
+    let inner = "a + b".parse::<TokenStream>().unwrap();
+    let group = TokenTree::from(TokenNode::Group(Group::new(Delimiter::None, inner)));
+    let stream = vec![
+        TokenTree::from(TokenNode::Op('*', Spacing::Alone)),
+        group.clone(),
+        TokenTree::from(TokenNode::Group(Group::new(Delimiter::None, TokenStream::empty()))),
+        TokenTree::from(TokenNode::Group(Group::new(Delimiter::Brace, group.into()))),
+    ].into_iter().collect::<TokenStream>();
+
+    // Plain `{}` drops the invisible delimiters...
+    assert_eq!(stream.to_string(), "*  a + b    {  a + b  }");
+    assert!(stream.to_string().parse::<TokenStream>().unwrap() != stream);
+
+    // ...while `{:#}` marks them so `parse_alternate` can put them back.
+    let s = format!("{:#}", stream);
+    assert_eq!(s, "* /*«*/ a + b /*»*/ /*«*/ /*»*/ { /*«*/ a + b /*»*/ }");
+    assert_eq!(TokenStream::parse_alternate(&s).unwrap(), stream);
+    assert_eq!(s.parse::<TokenStream>().unwrap(), stream.to_string().parse().unwrap());
+
+    let group = TokenStream::parse_alternate(&s).unwrap().into_iter().nth(1).unwrap();
+    assert_eq!((group.span.start().column, group.span.end().column), (2, 21));
+    match group.kind {
+        TokenNode::Group(ref g) => {
+            assert_eq!((g.span_open().start().column, g.span_open().end().column), (2, 8));
+            assert_eq!((g.span_close().start().column, g.span_close().end().column), (15, 21));
+        }
+        ref other => panic!("{:?}", other),
+    }
+
+    // Only `parse_alternate` reads the markers, where a stray one is
+    // unbalanced; elsewhere they are comments like any other.
+    assert!(TokenStream::parse_alternate("/*»*/").is_err());
+    assert!(TokenStream::parse_alternate("/*«*/ a").is_err());
+    assert_eq!(TokenStream::parse_alternate("/* « */ a").unwrap().to_string(), "a");
+    assert_eq!("/*«*/ a".parse::<TokenStream>().unwrap().to_string(), "a");
+    assert_eq!("/*»*/".parse::<TokenStream>().unwrap().to_string(), "");
+}
+
+#[test]
+fn joint_slash() {
+    // `/` followed by a joint `/` can't be printed as `//` without starting
+    // a comment, so the pair comes back as two separate operators.
+    let stream = vec![
+        TokenTree::from(TokenNode::Op('/', Spacing::Joint)),
+        TokenTree::from(TokenNode::Op('/', Spacing::Joint)),
+        TokenTree::from(TokenNode::Op('*', Spacing::Alone)),
+        TokenTree::from(TokenNode::Op('/', Spacing::Joint)),
+        TokenTree::from(TokenNode::Op('=', Spacing::Alone)),
+    ].into_iter().collect::<TokenStream>();
+    assert_eq!(stream.to_string(), "/ / * /=");
+    let reparsed = stream.to_string().parse::<TokenStream>().unwrap();
+    assert_eq!(reparsed.into_iter().count(), 5);
 }
 
 #[test]
@@ -62,3 +130,1125 @@ fn fail() {
     fail("' static");
     fail("'mut");
 }
+
+#[test]
+fn lex_errors() {
+    fn err(p: &str, kind: LexErrorKind, offset: usize) {
+        match p.parse::<TokenStream>() {
+            Ok(_) => panic!("should have failed to parse: {}", p),
+            Err(e) => {
+                assert_eq!(e.kind(), kind, "wrong kind for {:?}: {}", p, e);
+                assert_eq!(e.offset(), offset, "wrong offset for {:?}: {}", p, e);
+            }
+        }
+    }
+    err("1u80", LexErrorKind::InvalidSuffix, 1);
+    err("a 1x", LexErrorKind::InvalidSuffix, 3);
+    err("1f320", LexErrorKind::InvalidSuffix, 1);
+    err("0b102", LexErrorKind::InvalidNumber, 4);
+    err("1e", LexErrorKind::InvalidNumber, 2);
+    err("a \"foo", LexErrorKind::UnterminatedString, 2);
+    err("(\"\\q\")", LexErrorKind::InvalidEscape, 2);
+    err("'mut", LexErrorKind::InvalidLifetime, 0);
+    err("' static", LexErrorKind::InvalidLifetime, 0);
+    err("a /* b", LexErrorKind::UnterminatedComment, 2);
+    err("f(a, b", LexErrorKind::UnclosedDelimiter, 1);
+    err("f(a, b]", LexErrorKind::UnbalancedDelimiter, 6);
+    err("a }", LexErrorKind::UnbalancedDelimiter, 2);
+    err("a \\ b", LexErrorKind::UnexpectedCharacter, 2);
+
+    let e = "{\n  a(b\n}".parse::<TokenStream>().unwrap_err();
+    assert_eq!(e.expected(), Some(")"));
+    assert_eq!(e.span().start().line, 3);
+    assert_eq!(e.span().start().column, 0);
+    assert_eq!(e.to_string(), "unexpected closing delimiter, expected `)` at line 3, column 0");
+}
+
+#[test]
+fn span_test() {
+    fn check_spans(p: &str, mut lines: &[(usize, usize, usize, usize)]) {
+        let ts = p.parse::<TokenStream>().unwrap();
+        check_spans_internal(ts, &mut lines);
+    }
+
+    fn check_spans_internal(
+        ts: TokenStream,
+        lines: &mut &[(usize, usize, usize, usize)],
+    ) {
+        for i in ts {
+            if let Some((&(sline, scol, eline, ecol), rest)) = lines.split_first() {
+                *lines = rest;
+
+                let start = i.span.start();
+                assert_eq!(start.line, sline, "sline did not match for {}", i);
+                assert_eq!(start.column, scol, "scol did not match for {}", i);
+
+                let end = i.span.end();
+                assert_eq!(end.line, eline, "eline did not match for {}", i);
+                assert_eq!(end.column, ecol, "ecol did not match for {}", i);
+
+                match i.kind {
+                    TokenNode::Group(group) =>
+                        check_spans_internal(group.stream(), lines),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    check_spans("\
+/// This is a document comment
+testing 123
+{
+  testing 234
+}", &[
+        (1, 0, 1, 30),
+        (2, 0, 2, 7),
+        (2, 8, 2, 11),
+        (3, 0, 5, 1),
+        (4, 2, 4, 9),
+        (4, 10, 4, 13),
+    ]);
+}
+
+#[test]
+fn diagnostics() {
+    let ts = "struct Foo".parse::<TokenStream>().unwrap();
+    let span = ts.into_iter().nth(1).unwrap().span;
+
+    let diag = Diagnostic::spanned(span, Level::Error, "bad name")
+        .help("try `Bar`");
+    assert_eq!(diag.children().len(), 1);
+    assert_eq!(diag.to_string(), "error: bad name\nhelp: try `Bar`");
+
+    let tokens = diag.to_compile_error();
+    assert_eq!(tokens.to_string(), "compile_error ! { \"bad name\\nhelp: try `Bar`\" }");
+    for tt in tokens {
+        assert_eq!(tt.span.start().column, 7);
+        assert_eq!(tt.span.end().column, 10);
+    }
+
+    let warning = Diagnostic::new(Level::Warning, "unused");
+    assert!(warning.to_compile_error().is_empty());
+    assert!(warning.emit().is_empty());
+}
+
+#[test]
+fn accumulated_errors() {
+    let ts = "struct Foo { a: u8, b: u16 }".parse::<TokenStream>().unwrap();
+    let mut errors = Errors::new();
+    for tt in ts {
+        if let TokenNode::Term(ref term) = tt.kind {
+            errors.error(tt.span, format!("unexpected `{}`", term.as_str()));
+        }
+    }
+    errors.push(Diagnostic::new(Level::Note, "not rendered on stable"));
+    assert_eq!(errors.len(), 3);
+
+    let tokens = errors.to_compile_errors();
+    assert_eq!(tokens.to_string(),
+               "compile_error ! { \"unexpected `struct`\" } \
+                compile_error ! { \"unexpected `Foo`\" }");
+
+    assert!(Errors::new().finish().is_ok());
+    let mut errors = Errors::new();
+    errors.combine(Errors::from(Diagnostic::new(Level::Error, "a")));
+    errors.extend(vec![Diagnostic::new(Level::Error, "b")]);
+    let errors = errors.finish().unwrap_err();
+    assert_eq!(errors.iter().map(|d| d.message()).collect::<Vec<_>>(), ["a", "b"]);
+}
+
+#[test]
+fn quote() {
+    let name = Term::intern("Foo");
+    let ty = "Vec<u8>".parse::<TokenStream>().unwrap();
+    let tokens = quote! {
+        impl #name {
+            fn new(x: #ty) -> Self {
+                let _ = -1i32 + 'a';
+                x <<= 2;
+                Self::from(#ty::new())
+            }
+        }
+    };
+    assert_eq!(tokens.to_string(),
+               "impl Foo { \
+                fn new ( x : Vec < u8 > ) -> Self { \
+                let _ = - 1i32 + 'a' ; \
+                x <<= 2 ; \
+                Self :: from ( Vec < u8 > :: new ( ) ) \
+                } \
+                }");
+
+    let fields = vec![Term::intern("a"), Term::intern("b")];
+    let names = &fields;
+    let tys = vec!["u8", "u16"];
+    let none: Option<Term> = None;
+    let tokens = quote! {
+        struct S { #(#names: #tys),* }
+        #(#names)* #none
+    };
+    assert_eq!(tokens.to_string(), "struct S { a : \"u8\" , b : \"u16\" } a b");
+
+    assert!(quote!().is_empty());
+    assert_eq!(quote!(#(#fields);*).to_string(), "a ; b");
+    assert_eq!(5u8.to_token_stream().to_string(), "5u8");
+    assert_eq!(true.to_token_stream().to_string(), "true");
+
+    // Booleans are literals, whether lexed, quoted or interpolated.
+    let lexed = "true false".parse::<TokenStream>().unwrap();
+    assert_eq!(quote!(true false), lexed);
+    let (t, f) = (true, false);
+    assert_eq!(quote!(#t #f), lexed);
+    assert!(quote!(true).into_iter().all(|tt| match tt.kind {
+        TokenNode::Literal(l) => l.kind() == LiteralKind::Bool,
+        _ => false,
+    }));
+    parse::parse_with(quote!(true), |input| input.expect("true")).unwrap();
+    parse::parse_with(lexed, |input| {
+        input.expect("true")?;
+        input.expect("false")
+    }).unwrap();
+}
+
+#[test]
+fn cursor() {
+    let buffer = TokenBuffer::new("a: (b, [c]) + 'x' d".parse().unwrap());
+    let start = buffer.begin();
+
+    let (a, _, rest) = start.term().unwrap();
+    assert_eq!(a.as_str(), "a");
+    assert_eq!(rest.op().map(|(ch, _, _, _)| ch), Some(':'));
+    assert!(start.op().is_none());
+
+    // Looking ahead doesn't require consuming anything.
+    let plus = start.advance(3).unwrap();
+    assert_eq!(plus.op().map(|(ch, _, _, _)| ch), Some('+'));
+    assert_eq!(start.advance(5).unwrap().term().unwrap().0.as_str(), "d");
+    assert!(start.advance(6).unwrap().eof());
+    assert!(start.advance(7).is_none());
+
+    let paren = start.advance(2).unwrap();
+    assert!(paren.group(Delimiter::Brace).is_none());
+    let (inside, span, after) = paren.group(Delimiter::Parenthesis).unwrap();
+    assert_eq!(span.start().column, 3);
+    assert!(after == plus);
+    assert_eq!(inside.token_stream().to_string(), "b , [ c ]");
+
+    let (bracket, _, _) = inside.advance(2).unwrap().group(Delimiter::Bracket).unwrap();
+    let (c, _, end) = bracket.term().unwrap();
+    assert_eq!(c.as_str(), "c");
+    assert!(end.eof());
+    assert_eq!(end.span().start().column, 7);
+    assert!(end.exit().unwrap().eof());
+    assert!(end.exit().unwrap().exit().unwrap() == plus);
+    assert!(inside.exit().unwrap() == plus);
+    assert!(start.exit().is_none());
+
+    match plus.op() {
+        Some(('+', Spacing::Alone, _, rest)) => {
+            assert_eq!(rest.literal().unwrap().0.to_string(), "'x'");
+        }
+        _ => panic!("expected `+`"),
+    }
+
+    let (tt, _) = paren.token_tree().unwrap();
+    assert_eq!(tt.to_string(), "( b , [ c ] )");
+}
+
+#[test]
+fn parse_stream() {
+    #[derive(Debug)]
+    struct Field {
+        name: Term,
+        ty: TokenStream,
+    }
+
+    impl Parse for Field {
+        fn parse(input: ParseStream) -> parse::Result<Field> {
+            let name = input.parse()?;
+            input.expect(':')?;
+            let mut ty = Vec::new();
+            while !input.is_empty() && !input.peek(',') {
+                ty.push(input.parse::<TokenTree>()?);
+            }
+            Ok(Field { name: name, ty: ty.into_iter().collect() })
+        }
+    }
+
+    struct Struct {
+        name: Term,
+        fields: Punctuated<Field>,
+    }
+
+    impl Parse for Struct {
+        fn parse(input: ParseStream) -> parse::Result<Struct> {
+            let lookahead = input.lookahead1();
+            if lookahead.peek("struct") {
+                input.expect("struct")?;
+            } else if lookahead.peek("union") {
+                input.expect("union")?;
+            } else {
+                return Err(lookahead.error());
+            }
+            let name = input.parse()?;
+            let (_, fields) = input.braced(|content| {
+                Punctuated::parse_terminated(content, ',', Field::parse)
+            })?;
+            Ok(Struct { name: name, fields: fields })
+        }
+    }
+
+    let s: Struct = parse::parse_str("struct S { a: u8, b: Vec<u8>, }").unwrap();
+    assert_eq!(s.name.as_str(), "S");
+    assert!(s.fields.trailing_punct());
+    let fields = s.fields.iter()
+        .map(|f| format!("{}: {}", f.name.as_str(), f.ty))
+        .collect::<Vec<_>>();
+    assert_eq!(fields, ["a: u8", "b: Vec < u8 >"]);
+
+    let err = parse::parse_str::<Struct>("enum E {}").err().unwrap();
+    assert_eq!(err.message(), "unexpected token, expected one of: `struct`, `union`");
+    assert_eq!(err.span().start().column, 0);
+
+    let err = parse::parse_str::<Struct>("struct S { a b: u8 }").err().unwrap();
+    assert_eq!(err.message(), "unexpected token, expected `:`");
+    assert_eq!(err.span().start().column, 13);
+
+    let err = parse::parse_str::<Struct>("struct S { a }").err().unwrap();
+    assert_eq!(err.message(), "unexpected end of input, expected `:`");
+    assert_eq!(err.span().start().column, 9);
+    assert_eq!(err.to_compile_error().to_string(),
+               "compile_error ! { \"unexpected end of input, expected `:`\" }");
+
+    let err = parse::parse_str::<Struct>("struct S {} x").err().unwrap();
+    assert_eq!(err.message(), "unexpected token");
+    assert!(parse::parse_str::<Term>("1x").is_err());
+
+    // Group contents must be parsed in full, just like the top level.
+    let err = parse::parse_with("(a b) c".parse().unwrap(), |input| {
+        let (_, term) = input.parenthesized(Term::parse)?;
+        input.parse::<Term>()?;
+        Ok(term)
+    }).err().unwrap();
+    assert_eq!(err.message(), "unexpected token");
+    assert_eq!(err.span().start().column, 3);
+    let (_, term) = parse::parse_with("[a]".parse().unwrap(), |input| {
+        input.bracketed(Term::parse)
+    }).unwrap();
+    assert_eq!(term.as_str(), "a");
+
+    // Paths separated by a multi-character operator, and forking.
+    let path = parse::parse_with("a::b::c".parse().unwrap(), |input| {
+        let fork = input.fork();
+        fork.parse::<Term>()?;
+        assert!(fork.peek("::") && !input.peek("::"));
+        assert!(input.peek_nth(1, ':') && input.peek_nth(3, "b"));
+        Punctuated::parse_separated_nonempty(input, "::", Term::parse)
+    }).unwrap();
+    assert_eq!(path.iter().map(|t| t.as_str()).collect::<Vec<_>>(), ["a", "b", "c"]);
+    assert!(parse::parse_with(TokenStream::empty(), |input| {
+        Punctuated::parse_separated_nonempty(input, ',', Term::parse)
+    }).is_err());
+    assert!(parse::parse_with(": :".parse().unwrap(), |input| input.expect("::")).is_err());
+
+    // Strings match whole runs of joint ops, and nothing when empty.
+    parse::parse_with("a <= b >> c".parse().unwrap(), |input| {
+        assert!(!input.peek(""));
+        input.parse::<Term>()?;
+        assert!(!input.peek("<") && input.peek('<') && input.peek("<="));
+        input.expect("<=")?;
+        input.parse::<Term>()?;
+        assert!(!input.peek(">") && input.peek(">>"));
+        input.expect('>')?;
+        assert!(input.peek(">") && !input.peek(">>"));
+        input.expect(">")?;
+        input.parse::<Term>()
+    }).unwrap();
+    assert!(parse::parse_with(TokenStream::empty(), |input| input.expect("")).is_err());
+    parse::parse_with("f::<T>".parse().unwrap(), |input| {
+        input.parse::<Term>()?;
+        input.expect("::")?;
+        input.expect("<")?;
+        input.parse::<Term>()?;
+        input.expect(">")
+    }).unwrap();
+}
+
+#[test]
+fn equality() {
+    use std::collections::HashSet;
+
+    let a: TokenStream = "foo(1, \"x\") { bar::<'a> }".parse().unwrap();
+    let b: TokenStream = "foo ( 1 , \"x\" ) {\n    bar ::<'a >\n}".parse().unwrap();
+    assert_eq!(a, b);
+    assert!(!a.eq_with_spans(&b));
+    assert!(a.eq_with_spans(&a.clone()));
+
+    let c: TokenStream = "foo(1, \"x\") { bar::<'b> }".parse().unwrap();
+    let d: TokenStream = "foo[1, \"x\"] { bar::<'a> }".parse().unwrap();
+    assert!(a != c && a != d);
+
+    let built = quote! { foo(1, "x") };
+    let parsed: TokenStream = "foo(1, \"x\")".parse().unwrap();
+    assert_eq!(built, parsed);
+
+    // Literals are compared by their text, terms by symbol.
+    assert_eq!(Literal::u8(1), Literal::u8(1));
+    assert!(Literal::u8(1) != Literal::integer(1));
+    assert_eq!(Term::intern("foo"), Term::intern("foo"));
+    assert!(Term::intern("foo") != Term::intern("bar"));
+
+    // `: :` and `::` only differ in spacing.
+    let joint: TokenStream = "::".parse().unwrap();
+    let alone: TokenStream = ": :".parse().unwrap();
+    assert!(joint != alone);
+
+    let set = vec![a.clone(), b, c].into_iter().collect::<HashSet<_>>();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(&a));
+}
+
+#[test]
+fn literal_values() {
+    fn lit(s: &str) -> Literal {
+        match s.parse::<TokenStream>().unwrap().into_iter().next().unwrap().kind {
+            TokenNode::Literal(l) => l,
+            _ => panic!("not a literal: {}", s),
+        }
+    }
+    fn kind<T>(r: Result<T, proc_macro2::LitError>) -> LitErrorKind {
+        r.err().unwrap().kind()
+    }
+
+    assert_eq!(lit(r#""a\n\"\x41\u{1F600}""#).string_value().unwrap(), "a\n\"A\u{1F600}");
+    assert_eq!(lit("\"a\\\n     b\"").string_value().unwrap(), "ab");
+    assert_eq!(lit(r###"r#"a\n"b"#"###).string_value().unwrap(), "a\\n\"b");
+    assert_eq!(Literal::string("x\ty").string_value().unwrap(), "x\ty");
+    assert_eq!(lit(r#"b"a\xff\0""#).byte_string_value().unwrap(), b"a\xff\0");
+    assert_eq!(lit(r##"br#"a\"#"##).byte_string_value().unwrap(), b"a\\");
+    assert_eq!(lit(r"'\''").char_value().unwrap(), '\'');
+    assert_eq!(lit("'\u{e9}'").char_value().unwrap(), '\u{e9}');
+    assert_eq!(lit(r"b'\x7f'").byte_value().unwrap(), 0x7f);
+    assert_eq!(Literal::byte_char(0).byte_value().unwrap(), 0);
+
+    assert_eq!(lit("1_000").u128_value().unwrap(), (1000, None));
+    assert_eq!(lit("0xffu8").u128_value().unwrap(), (255, Some("u8".to_string())));
+    assert_eq!(lit("0o17").u128_value().unwrap(), (15, None));
+    assert_eq!(lit("0b1010_1010").u128_value().unwrap(), (170, None));
+    assert_eq!(Literal::i32(-5).i128_value().unwrap(), (-5, Some("i32".to_string())));
+    assert_eq!(kind(Literal::i32(-5).u128_value()), LitErrorKind::Overflow);
+    assert_eq!(kind(lit("340282366920938463463374607431768211456").u128_value()),
+               LitErrorKind::Overflow);
+    assert_eq!(kind(lit("170141183460469231731687303715884105728").i128_value()),
+               LitErrorKind::Overflow);
+
+    // Suffixed values must fit the suffix type.
+    assert_eq!(kind(lit("256u8").u128_value()), LitErrorKind::Overflow);
+    assert_eq!(kind(lit("0x1_00u8").i128_value()), LitErrorKind::Overflow);
+    assert_eq!(kind(lit("128i8").i128_value()), LitErrorKind::Overflow);
+    assert_eq!(kind(lit("128i8").u128_value()), LitErrorKind::Overflow);
+    assert_eq!(lit("127i8").u128_value().unwrap(), (127, Some("i8".to_string())));
+    assert_eq!(Literal::i8(-128).i128_value().unwrap(), (-128, Some("i8".to_string())));
+    assert_eq!(kind(Literal::i64(-1).u128_value()), LitErrorKind::Overflow);
+    assert_eq!(kind(lit("65536u16").u128_value()), LitErrorKind::Overflow);
+    assert_eq!(kind(lit("2147483648i32").i128_value()), LitErrorKind::Overflow);
+    assert_eq!(lit("4294967295u32").u128_value().unwrap().0, 4294967295);
+
+    assert_eq!(lit("1.5").f64_value().unwrap(), (1.5, None));
+    assert_eq!(lit("2.5e1_f32").f64_value().unwrap(), (25.0, Some("f32".to_string())));
+    assert_eq!(lit("1f64").f64_value().unwrap(), (1.0, Some("f64".to_string())));
+    assert_eq!(Literal::f32(-0.5).f64_value().unwrap(), (-0.5, Some("f32".to_string())));
+    assert_eq!(kind(lit("1e400").f64_value()), LitErrorKind::Overflow);
+
+    assert_eq!(kind(lit("1").f64_value()), LitErrorKind::WrongKind);
+    assert_eq!(kind(lit("1.0").u128_value()), LitErrorKind::WrongKind);
+    assert_eq!(kind(lit("\"x\"").char_value()), LitErrorKind::WrongKind);
+    assert_eq!(kind(lit("'x'").string_value()), LitErrorKind::WrongKind);
+}
+
+#[test]
+fn literal_kinds() {
+    let stream: TokenStream = r###"
+        "a" r"b" r##"c"## b"d" br#"e"# 'f' b'g' 1 0x1u8 1.5 2e3 3f32 true
+        /// doc
+    "###.parse().unwrap();
+    let kinds = stream.into_iter().map(|tt| match tt.kind {
+        TokenNode::Literal(l) => (l.kind(), l.suffix()),
+        _ => panic!("not a literal: {}", tt),
+    }).collect::<Vec<_>>();
+    let suffix = |s: &str| Some(s.to_string());
+    assert_eq!(kinds, [
+        (LiteralKind::Str, None),
+        (LiteralKind::RawStr { pounds: 0 }, None),
+        (LiteralKind::RawStr { pounds: 2 }, None),
+        (LiteralKind::ByteStr, None),
+        (LiteralKind::RawByteStr { pounds: 1 }, None),
+        (LiteralKind::Char, None),
+        (LiteralKind::Byte, None),
+        (LiteralKind::Int, None),
+        (LiteralKind::Int, suffix("u8")),
+        (LiteralKind::Float, None),
+        (LiteralKind::Float, None),
+        (LiteralKind::Float, suffix("f32")),
+        (LiteralKind::Bool, None),
+        (LiteralKind::DocComment, None),
+    ]);
+
+    // Constructed literals, including those from `quote!`, agree with the
+    // lexer.
+    assert_eq!(Literal::u8(1).kind(), LiteralKind::Int);
+    assert_eq!(Literal::u8(1).suffix(), suffix("u8"));
+    assert_eq!(Literal::float(1.0).kind(), LiteralKind::Float);
+    assert_eq!(Literal::float(1.0).f64_value().unwrap(), (1.0, None));
+    assert_eq!(Literal::raw_string("x", 3).kind(), LiteralKind::RawStr { pounds: 3 });
+    assert_eq!(Literal::byte_char(b'x').kind(), LiteralKind::Byte);
+    assert_eq!(Literal::doccomment("/// x").kind(), LiteralKind::DocComment);
+    let quoted = quote!(1.0e3_f64 -7i8 r#"x"#);
+    let kinds = quoted.into_iter().filter_map(|tt| match tt.kind {
+        TokenNode::Literal(l) => Some((l.kind(), l.suffix())),
+        _ => None,
+    }).collect::<Vec<_>>();
+    assert_eq!(kinds, [
+        (LiteralKind::Float, suffix("f64")),
+        (LiteralKind::Int, suffix("i8")),
+        (LiteralKind::RawStr { pounds: 1 }, None),
+    ]);
+}
+
+#[test]
+fn raw_identifiers() {
+    let stream: TokenStream = "r#match r#foo r #x r#\"s\"#".parse().unwrap();
+    let trees = stream.clone().into_iter().map(|tt| tt.kind).collect::<Vec<_>>();
+    match (&trees[0], &trees[1], &trees[2]) {
+        (&TokenNode::Term(ref a), &TokenNode::Term(ref b), &TokenNode::Term(ref r)) => {
+            assert!(a.is_raw() && b.is_raw() && !r.is_raw());
+            assert_eq!(a.as_str(), "match");
+            assert_eq!(*a, Term::intern_raw("match"));
+            assert!(*b != Term::intern("foo"));
+            assert_eq!(r.as_str(), "r");
+        }
+        _ => panic!("{:?}", trees),
+    }
+    match trees[5] {
+        TokenNode::Literal(ref l) => assert_eq!(l.kind(), LiteralKind::RawStr { pounds: 1 }),
+        _ => panic!("{:?}", trees),
+    }
+    assert_eq!(stream.to_string(), "r#match r#foo r # x r#\"s\"#");
+    assert_eq!(Term::intern_raw("type").to_string(), "r#type");
+
+    // An `r#` prefix always makes a raw term, never part of the name.
+    let prefixed = Term::intern("r#foo");
+    assert!(prefixed.is_raw());
+    assert_eq!(prefixed.as_str(), "foo");
+    assert_eq!(prefixed, Term::intern_raw("foo"));
+
+    for bad in &["r#self", "r#super", "r#Self", "r#crate", "r#_"] {
+        let err = bad.parse::<TokenStream>().err().unwrap();
+        assert_eq!(err.kind(), LexErrorKind::InvalidRawIdent, "{}", bad);
+    }
+
+    let field = Term::intern_raw("type");
+    assert_eq!(quote!(x.#field = r#loop;).to_string(), "x . r#type = r#loop ;");
+
+    assert!(parse::parse_str::<Term>("r#struct").unwrap().is_raw());
+    assert!(parse::parse_with("r#struct".parse().unwrap(), |input| input.expect("struct")).is_err());
+}
+
+#[test]
+fn editions() {
+    fn lex(src: &str, edition: Edition) -> Result<TokenStream, LexErrorKind> {
+        TokenStream::parse_with_edition(src, edition).map_err(|e| e.kind())
+    }
+
+    // Only keywords of the chosen edition are rejected as lifetimes.
+    assert!(lex("'async", Edition::Edition2015).is_ok());
+    assert_eq!(lex("'async", Edition::Edition2018).err(), Some(LexErrorKind::InvalidLifetime));
+    assert!(lex("'try 'dyn", Edition::Edition2015).is_ok());
+    assert!(lex("'try", Edition::Edition2018).is_err());
+    assert!(lex("'gen", Edition::Edition2021).is_ok());
+    assert!(lex("'gen", Edition::Edition2024).is_err());
+    assert!(lex("'proc 'pure 'alignof", Edition::Edition2015).is_ok());
+    assert!(lex("'box", Edition::Edition2015).is_err());
+    assert!(lex("'static '_", Edition::Edition2024).is_ok());
+
+    // Reserved prefixes.
+    for src in &["foo#bar", "foo\"x\"", "k'a'", "z#", "r#foo\"x\"", "r#foo#", "c'x'"] {
+        assert!(lex(src, Edition::Edition2018).is_ok(), "{}", src);
+        assert_eq!(lex(src, Edition::Edition2021).err(), Some(LexErrorKind::ReservedPrefix),
+                   "{}", src);
+    }
+    assert!(lex("r#\"x\"# br\"y\" b'z' r#foo foo #bar", Edition::Edition2021).is_ok());
+
+    // C strings are literals since 2021, and an identifier and a string before.
+    let kinds = |src: &str, edition| lex(src, edition).unwrap().into_iter()
+        .map(|tt| match tt.kind {
+            TokenNode::Literal(l) => Some(l.kind()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(kinds("c\"x\" cr#\"x\"# cr\"\"", Edition::Edition2021),
+               [Some(LiteralKind::CStr),
+                Some(LiteralKind::RawCStr { pounds: 1 }),
+                Some(LiteralKind::RawCStr { pounds: 0 })]);
+    assert_eq!(kinds("c\"x\"", Edition::Edition2018), [None, Some(LiteralKind::Str)]);
+    assert!(lex("cr#\"x\"#", Edition::Edition2018).is_ok());
+    assert_eq!(lex("c\"\\q\"", Edition::Edition2021).err(), Some(LexErrorKind::InvalidEscape));
+
+    assert_eq!(lex("'async", Edition::default()).unwrap(), "'async".parse().unwrap());
+}
+
+#[test]
+fn keywords() {
+    let class = |s: &str, e| Term::intern(s).keyword_class(e);
+    assert_eq!(class("fn", Edition::Edition2015), Some(KeywordClass::Strict));
+    assert_eq!(class("abstract", Edition::Edition2015), Some(KeywordClass::Reserved));
+    assert_eq!(class("union", Edition::Edition2015), Some(KeywordClass::Weak));
+    assert_eq!(class("dyn", Edition::Edition2015), Some(KeywordClass::Weak));
+    assert_eq!(class("dyn", Edition::Edition2018), Some(KeywordClass::Strict));
+    assert_eq!(class("async", Edition::Edition2015), None);
+    assert_eq!(class("try", Edition::Edition2018), Some(KeywordClass::Reserved));
+    assert_eq!(class("gen", Edition::Edition2021), None);
+    assert_eq!(class("gen", Edition::Edition2024), Some(KeywordClass::Reserved));
+    assert_eq!(class("foo", Edition::Edition2024), None);
+    assert_eq!(Term::intern_raw("fn").keyword_class(Edition::Edition2015), None);
+
+    assert!(Term::intern("async").is_keyword());
+    assert!(!Term::intern("union").is_keyword());
+    assert!(!Term::intern_raw("match").is_keyword());
+    assert!(!Term::intern("'static").is_keyword());
+    assert!(Term::intern("'a").is_lifetime());
+    assert!(!Term::intern("a").is_lifetime());
+
+    let ident = |s: &str| Term::intern_ident(s).to_string();
+    assert_eq!(ident("foo"), "foo");
+    assert_eq!(ident("type"), "r#type");
+    assert_eq!(ident("await"), "r#await");
+    assert_eq!(ident("self"), "self_");
+    assert_eq!(ident("_"), "__");
+    assert_eq!(ident("foo-bar baz"), "foo_bar_baz");
+    assert_eq!(ident("1x"), "_1x");
+    assert_eq!(ident(""), "__");
+    assert_eq!(ident("union"), "union");
+}
+
+#[test]
+fn lifetimes() {
+    let stream: TokenStream = "fn f<'a, 'static>(x: &'a u8) { 'outer: loop {} }".parse().unwrap();
+    let lifetimes = TokenBuffer::new(stream.clone()).begin().token_stream().into_iter()
+        .flat_map(|tt| match tt.kind {
+            TokenNode::Group(group) => group.stream().into_iter().collect(),
+            _ => vec![tt],
+        })
+        .filter_map(|tt| match tt.kind {
+            TokenNode::Lifetime(l) => Some(l),
+            TokenNode::Term(t) => {
+                assert!(!t.as_str().starts_with('\''));
+                None
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lifetimes, [Lifetime::new("'a"), Lifetime::new("'static"),
+                           Lifetime::new("'a"), Lifetime::new("'outer")]);
+    assert_eq!(lifetimes[3].name(), "outer");
+    assert_eq!(stream.to_string(),
+               "fn f < 'a , 'static > ( x : & 'a u8 ) { 'outer : loop { } }");
+
+    let a = Lifetime::new("'a");
+    assert_eq!(quote!(&#a T &'b U).to_string(), "& 'a T & 'b U");
+    assert!(quote!('b).into_iter().all(|tt| match tt.kind {
+        TokenNode::Lifetime(l) => l.as_str() == "'b",
+        _ => false,
+    }));
+    assert_eq!(parse::parse_str::<Lifetime>("'x").unwrap(), Lifetime::new("'x"));
+    assert!(parse::parse_str::<Lifetime>("x").is_err());
+    assert!(parse::parse_str::<Term>("'x").is_err());
+
+    for bad in &["a", "'", "'1", "'fn", "'a b"] {
+        assert!(std::panic::catch_unwind(|| Lifetime::new(bad)).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn groups() {
+    let stream: TokenStream = "f(a,\n  [b] )".parse().unwrap();
+    let group = match stream.into_iter().nth(1).unwrap().kind {
+        TokenNode::Group(group) => group,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(group.delimiter(), Delimiter::Parenthesis);
+    let (open, close) = (group.span_open(), group.span_close());
+    assert_eq!((open.start().line, open.start().column), (1, 1));
+    assert_eq!((open.end().line, open.end().column), (1, 2));
+    assert_eq!((close.start().line, close.start().column), (2, 6));
+    assert_eq!((close.end().line, close.end().column), (2, 7));
+    assert_eq!((group.span().start().column, group.span().end().column), (1, 7));
+
+    let inner = group.stream().into_iter().nth(2).unwrap();
+    match inner.kind {
+        TokenNode::Group(ref g) => {
+            assert_eq!(g.span_open().start().column, 2);
+            assert_eq!(g.span_close().start().column, 4);
+            assert!(g.span() == inner.span);
+        }
+        ref other => panic!("{:?}", other),
+    }
+
+    // Delimiter spans survive a trip through a buffer.
+    let tt = TokenBuffer::new(TokenStream::from(TokenTree::from(TokenNode::Group(group.clone()))))
+        .begin().token_tree().unwrap().0;
+    match tt.kind {
+        TokenNode::Group(g) => assert!(g.span_close() == close),
+        other => panic!("{:?}", other),
+    }
+
+    // Moving the tree doesn't move its delimiters, in or out of a buffer.
+    let mut moved = TokenTree::from(TokenNode::Group(group.clone()));
+    assert!(moved.span == group.span());
+    moved.span = Span::call_site();
+    let buffer = TokenBuffer::new(TokenStream::from(moved));
+    assert!(buffer.begin().span() == Span::call_site());
+    let tt = buffer.begin().token_tree().unwrap().0;
+    assert!(tt.span == Span::call_site());
+    match tt.kind {
+        TokenNode::Group(g) => {
+            assert!(g.span_open() == open && g.span_close() == close);
+            assert_eq!((g.span().start().column, g.span().end().column), (1, 7));
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let mut built = Group::new(Delimiter::Bracket, quote!(x));
+    assert_eq!(built.to_string(), "[ x ]");
+    built.set_span(open);
+    assert!(built.span_close() == open);
+    assert_eq!(built, Group::new(Delimiter::Bracket, quote!(x)));
+    assert!(TokenTree::from(TokenNode::Group(built)).span == open);
+}
+
+#[test]
+fn operators() {
+    let stream = "a <<= b ..= c :: d -> e => f < <= &&& !x".parse::<TokenStream>().unwrap();
+    let buffer = TokenBuffer::new(stream);
+    let mut cursor = buffer.begin();
+    let mut ops = Vec::new();
+    while !cursor.eof() {
+        if let Some((op, span, rest)) = op::glue(cursor) {
+            ops.push((op, span.start().column, span.end().column));
+            cursor = rest;
+        } else {
+            cursor = cursor.skip().unwrap();
+        }
+    }
+    assert_eq!(ops, vec![
+        ("<<=", 2, 5),
+        ("..=", 8, 11),
+        ("::", 14, 16),
+        ("->", 19, 21),
+        ("=>", 24, 26),
+        ("<", 29, 30),
+        ("<=", 31, 33),
+        ("&&", 34, 36),
+        ("&", 36, 37),
+        ("!", 38, 39),
+    ]);
+
+    let split = op::split("<<=", Span::call_site()).unwrap();
+    assert_eq!(split, "<<=".parse::<TokenStream>().unwrap());
+    let spacing = split.into_iter().map(|tt| match tt.kind {
+        TokenNode::Op(_, spacing) => spacing,
+        other => panic!("{:?}", other),
+    }).collect::<Vec<_>>();
+    assert_eq!(spacing, vec![Spacing::Joint, Spacing::Joint, Spacing::Alone]);
+    assert_eq!(op::split("+", Span::call_site()).unwrap().to_string(), "+");
+    assert!(op::split("<>", Span::call_site()).is_none());
+    assert!(op::split("a", Span::call_site()).is_none());
+    assert!(op::is_operator("..."));
+}
+
+#[test]
+fn pretty() {
+    let stream = quote! {
+        #[derive(Debug)]
+        pub struct Foo<'a, T: Clone> { a: &'a T, b: Vec<u8>, }
+        impl<'a, T> ::std::fmt::Display for Foo<'a, T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let x = -1 + *self.a;
+                if x < 3 && !y { return Err(e)?; } else { write!(f, "{}", x) }
+                self.b.iter().map(|x| x * 2).collect::<Vec<_>>();
+            }
+        }
+        use a::{b, c};
+    };
+    assert_eq!(stream.pretty(), "\
+#[derive(Debug)]
+pub struct Foo<'a, T: Clone> {
+    a: &'a T,
+    b: Vec<u8>,
+}
+impl<'a, T> ::std::fmt::Display for Foo<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let x = -1 + *self.a;
+        if x < 3 && !y {
+            return Err(e)?;
+        } else {
+            write!(f, \"{}\", x)
+        }
+        self.b.iter().map(|x| x * 2).collect::<Vec<_>>();
+    }
+}
+use a::{b, c};
+");
+
+    // A `<` only opens a generic list in a type, after `::`, or after the
+    // name of an item being defined.
+    let pretty = |src: &str| src.parse::<TokenStream>().unwrap().pretty();
+    assert_eq!(pretty("const C: bool = A < B;"), "const C: bool = A < B;\n");
+    assert_eq!(pretty("if a < b && c > d { x }"), "if a < b && c > d {\n    x\n}\n");
+    assert_eq!(pretty("match x { A => a < b, B(y) if y < B => C < D }"),
+               "match x {\n    A => a < b,\n    B(y) if y < B => C < D\n}\n");
+    assert_eq!(pretty("let f = || A < B;"), "let f = || A < B;\n");
+    assert_eq!(pretty("let f = |x: Vec<u8>| x.len() < N;"),
+               "let f = |x: Vec<u8>| x.len() < N;\n");
+    assert_eq!(pretty("let v: Vec<u8> = A < B;"), "let v: Vec<u8> = A < B;\n");
+    assert_eq!(pretty("type A = B<C>; let d = E < F;"), "type A = B<C>;\nlet d = E < F;\n");
+    assert_eq!(pretty("struct S(Vec<u8>); enum E { A(Vec<u8>) }"),
+               "struct S(Vec<u8>);\nenum E {\n    A(Vec<u8>)\n}\n");
+    assert_eq!(pretty("fn f(x: &mut Vec<T>) -> Result<(A<B>, C), E> { x as Vec<u8> }"),
+               "fn f(x: &mut Vec<T>) -> Result<(A<B>, C), E> {\n    x as Vec<u8>\n}\n");
+    assert_eq!(pretty("<T as Trait<U>>::f(a < b)"), "<T as Trait<U>>::f(a < b)\n");
+
+    let doc = "/// Docs\nfn f() {}".parse::<TokenStream>().unwrap();
+    assert_eq!(doc.pretty(), "/// Docs\nfn f() {}\n");
+    assert_eq!(TokenStream::empty().pretty(), "");
+}
+
+#[test]
+fn source_text() {
+    let src = "fn f(a: u8 /* the a */,\n     b: &str) { \"s\\n\" }";
+    let stream = src.parse::<TokenStream>().unwrap();
+    let trees = stream.into_iter().collect::<Vec<_>>();
+    assert_eq!(trees[0].span.source_text().unwrap(), "fn");
+    assert_eq!(trees[2].span.source_text().unwrap(), "(a: u8 /* the a */,\n     b: &str)");
+    match trees[3].kind {
+        TokenNode::Group(ref g) => {
+            let lit = g.stream().into_iter().next().unwrap();
+            assert_eq!(lit.span.source_text().unwrap(), "\"s\\n\"");
+            assert_eq!(g.span_close().source_text().unwrap(), "}");
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_eq!(Span::call_site().source_text(), None);
+}
+
+#[test]
+fn source_files() {
+    let stream = proc_macro2::parse_file("src/foo.rs", "fn foo() {}").unwrap();
+    let tt = stream.into_iter().nth(1).unwrap();
+    let file = tt.span.source_file();
+    assert_eq!(file.path(), std::path::PathBuf::from("src/foo.rs"));
+    // Only a path which exists makes a real file.
+    assert!(!file.is_real());
+    let real = proc_macro2::parse_file("src/lib.rs", "a").unwrap().into_iter().next().unwrap();
+    assert!(real.span.source_file().is_real());
+    assert_eq!(tt.span.source_text().unwrap(), "foo");
+    assert_eq!(file, tt.span.source_file());
+
+    let parsed = "a".parse::<TokenStream>().unwrap().into_iter().next().unwrap();
+    let other = parsed.span.source_file();
+    assert!(!other.is_real());
+    assert!(other != file);
+    assert!(!Span::call_site().source_file().is_real());
+
+    // Each call registers a new file, even with the same path.
+    let again = proc_macro2::parse_file("src/foo.rs", "fn").unwrap();
+    assert!(again.into_iter().next().unwrap().span.source_file() != file);
+
+    let err = proc_macro2::parse_file("src/bar.rs", "a\n  )").unwrap_err();
+    assert_eq!(err.span().source_file().path(), std::path::PathBuf::from("src/bar.rs"));
+    assert_eq!(err.span().start().line, 2);
+
+    let edition = |e| proc_macro2::parse_file_with_edition("src/foo.rs", "'async", e).is_ok();
+    assert!(edition(Edition::Edition2015));
+    assert!(!edition(Edition::Edition2018));
+}
+
+#[test]
+fn span_join_and_hygiene() {
+    let trees = "#[attr(x)] fn f() {}".parse::<TokenStream>().unwrap()
+        .into_iter().collect::<Vec<_>>();
+    let attr = trees[0].span.join(trees[1].span).unwrap();
+    assert_eq!(attr.source_text().unwrap(), "#[attr(x)]");
+    assert_eq!((attr.start().column, attr.end().column), (0, 10));
+    let other = "a".parse::<TokenStream>().unwrap().into_iter().next().unwrap();
+    assert!(trees[0].span.join(other.span).is_none());
+
+    let call = Span::call_site();
+    assert!(Span::def_site() != call);
+    assert!(Span::mixed_site() != call);
+    assert!(Span::mixed_site() != Span::def_site());
+
+    let def = trees[2].span.resolved_at(Span::def_site());
+    assert!(def != trees[2].span);
+    assert_eq!(def.source_text().unwrap(), "fn");
+    assert!(def.resolved_at(call) == trees[2].span);
+    assert!(Span::def_site().located_at(trees[2].span) == def);
+
+    // Joining keeps the context of the first span.
+    let joined = def.join(trees[3].span).unwrap();
+    assert_eq!(joined.source_text().unwrap(), "fn f");
+    assert!(joined.resolved_at(call) == trees[2].span.join(trees[3].span).unwrap());
+    assert!(joined != trees[2].span.join(trees[3].span).unwrap());
+
+    let a = TokenTree { span: call, kind: TokenNode::Term(Term::intern("a")) };
+    let b = TokenTree { span: Span::def_site(), kind: TokenNode::Term(Term::intern("a")) };
+    assert_eq!(a, b);
+    assert!(!a.eq_with_spans(&b));
+}
+
+#[test]
+fn hygiene() {
+    use std::thread;
+
+    fn def_site(stream: TokenStream) -> TokenStream {
+        stream.into_iter().map(|tt| {
+            let kind = match tt.kind {
+                TokenNode::Group(g) => {
+                    TokenNode::Group(Group::new(g.delimiter(), def_site(g.stream())))
+                }
+                kind => kind,
+            };
+            TokenTree { span: Span::def_site(), kind: kind }
+        }).collect()
+    }
+
+    // A fresh thread, so that these are its first expansions.
+    thread::spawn(|| {
+        let mut stream = def_site(quote!(let mut x: Vec<u8> = get::<'a, 'static>(y);));
+        stream.extend(quote!(let x = 1; use_it(x);));
+        stream.extend(def_site(quote!(x self)));
+        stream.extend(quote!(__h0_x));
+
+        // The name the user already has is skipped.
+        assert_eq!(hygiene::resolve(stream.clone()).to_string(),
+                   "let mut __h0_x_ : Vec < u8 > = get :: < 'a , 'static > ( y ) ; \
+                    let x = 1 ; use_it ( x ) ; __h0_x_ self __h0_x");
+
+        // Another expansion picks different names.
+        assert_eq!(hygiene::resolve(stream).to_string(),
+                   "let mut __h1_x : Vec < u8 > = get :: < 'a , 'static > ( y ) ; \
+                    let x = 1 ; use_it ( x ) ; __h1_x self __h0_x");
+
+        // Only a name which is a whole pattern is a local variable, not the
+        // types and paths around `for` and `mut`.
+        let stream = def_site(quote! {
+            impl Clone for T {}
+            let p: *mut T = f::<T>(&mut y);
+            fn g<F>(mut m: &'c mut u8) where for<'b> F: Fn(&'b u8) {}
+            for i in v {}
+            let ref mut r = i;
+            static mut S: u8 = 0;
+        });
+        assert_eq!(hygiene::resolve(stream).to_string(),
+                   "impl Clone for T { } \
+                    let __h2_p : * mut T = f :: < T > ( & mut y ) ; \
+                    fn g < F > ( mut __h2_m : & 'c mut u8 ) \
+                    where for < '__h2_b > F : Fn ( & '__h2_b u8 ) { } \
+                    for __h2_i in v { } \
+                    let ref mut __h2_r = __h2_i ; \
+                    static mut S : u8 = 0 ;");
+
+        // Names within larger patterns aren't recognised.
+        let stream = def_site(quote! {
+            let (a, b) = t;
+            let c = |d| d;
+            if let Some(e) = o { e }
+        });
+        assert_eq!(hygiene::resolve(stream).to_string(),
+                   "let ( a , b ) = t ; \
+                    let __h3_c = | d | d ; \
+                    if let Some ( e ) = o { e }");
+
+        // Fields, methods and path segments with a local's name are left
+        // alone, and a field shorthand keeps its field name.
+        let stream = def_site(quote! {
+            let len = v.len();
+            let value = self.value;
+            let s = S { value, len: len };
+            for i in 0..len { a::len(i) }
+            if len { value } else { S::<T> { value } }
+            struct W { value: u8 }
+        });
+        assert_eq!(hygiene::resolve(stream).to_string(),
+                   "let __h4_len = v . len ( ) ; \
+                    let __h4_value = self . value ; \
+                    let __h4_s = S { value : __h4_value , len : __h4_len } ; \
+                    for __h4_i in 0 .. __h4_len { a :: len ( __h4_i ) } \
+                    if __h4_len { __h4_value } else { S :: < T > { value : __h4_value } } \
+                    struct W { value : u8 }");
+
+        // Only lifetimes and labels declared in the stream are renamed.
+        let stream = def_site(quote! {
+            fn f(x: &'_ u8) -> &'_ u8 { x }
+            impl<'a, 'b: 'a> T<'a> for S<'b> where for<'c> &'c u8: X {}
+            fn g<'d, F: Fn(&'d u8) -> &'d u8>(x: &'e u8) {}
+            'l: loop { break 'l; }
+        });
+        assert_eq!(hygiene::resolve(stream).to_string(),
+                   "fn f ( x : & '_ u8 ) -> & '_ u8 { x } \
+                    impl < '__h5_a , '__h5_b : '__h5_a > T < '__h5_a > for S < '__h5_b > \
+                    where for < '__h5_c > & '__h5_c u8 : X { } \
+                    fn g < '__h5_d , F : Fn ( & '__h5_d u8 ) -> & '__h5_d u8 > \
+                    ( x : & 'e u8 ) { } \
+                    '__h5_l : loop { break '__h5_l ; }");
+    }).join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "thread-safe")]
+fn send_sync() {
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Term>();
+    assert_send_sync::<Literal>();
+    assert_send_sync::<TokenStream>();
+
+    let handles = (0..4).map(|i| {
+        thread::spawn(move || {
+            let src = format!("fn f{}(x: u8) {{ shared }}", i);
+            (Term::intern("shared"), src.parse::<TokenStream>().unwrap())
+        })
+    }).collect::<Vec<_>>();
+    for (i, handle) in handles.into_iter().enumerate() {
+        let (term, stream) = handle.join().unwrap();
+        assert_eq!(term.as_str(), "shared");
+        assert_eq!(term, Term::intern("shared"));
+        let name = stream.into_iter().nth(1).unwrap();
+        assert_eq!(name.to_string(), format!("f{}", i));
+        assert_eq!(name.span.source_text().unwrap(), format!("f{}", i));
+    }
+}
+
+#[test]
+#[cfg(not(feature = "thread-safe"))]
+fn interner_scopes() {
+    use std::thread;
+
+    // A fresh thread, so that the counts start from an empty interner.
+    thread::spawn(|| {
+        let before = interner::stats().unwrap();
+        let kept = Term::intern("kept");
+        let after_kept = interner::stats().unwrap();
+        assert_eq!(after_kept.symbols, before.symbols + 1);
+        assert_eq!(after_kept.bytes, before.bytes + 4);
+
+        let mut escaped = kept.clone();
+        let printed = interner::with_interner(|| {
+            let stream = "fn temporary(kept: u8) {}".parse::<TokenStream>().unwrap();
+            escaped = Term::intern("escaped");
+            interner::with_interner(|| Term::intern("nested"));
+            assert_eq!(interner::stats().unwrap().symbols, after_kept.symbols + 4);
+            stream.to_string()
+        });
+        assert_eq!(printed, "fn temporary ( kept : u8 ) { }");
+        assert_eq!(kept.as_str(), "kept");
+
+        // A term which escapes its scope keeps its own string, which is freed
+        // along with the last term holding it.
+        assert_eq!(interner::stats().unwrap(), interner::Stats { kept: 7, ..after_kept });
+        assert_eq!(escaped.as_str(), "escaped");
+        let again = Term::intern("escaped");
+        assert_eq!(again, escaped);
+        assert_eq!(interner::stats().unwrap().symbols, after_kept.symbols + 1);
+        drop(escaped);
+        assert_eq!(interner::stats().unwrap().kept, 0);
+
+        // The same goes for the terms in a token stream.
+        let stream = interner::with_interner(|| "a + b".parse::<TokenStream>().unwrap());
+        assert_eq!(interner::stats().unwrap().kept, 2);
+        assert_eq!(stream.to_string(), "a + b");
+        drop(stream);
+        assert_eq!(interner::stats().unwrap().kept, 0);
+
+        // Source lexed in a scope is freed too, and its spans act like
+        // `Span::call_site()` from then on.
+        let lit = interner::with_interner(|| {
+            let stream = proc_macro2::parse_file("src/lib.rs", "\n  1").unwrap();
+            let lit = stream.into_iter().next().unwrap();
+            assert_eq!(lit.span.source_text().unwrap(), "1");
+            assert_eq!(lit.span.start().line, 2);
+            lit
+        });
+        let fresh = "22".parse::<TokenStream>().unwrap().into_iter().next().unwrap();
+        assert_eq!(fresh.span.source_text().unwrap(), "22");
+        assert_eq!(lit.span.source_text(), None);
+        assert_eq!((lit.span.start().line, lit.span.start().column), (1, 0));
+        assert!(lit.span.source_file() == Span::call_site().source_file());
+        assert!(lit.span.join(fresh.span).is_none());
+    }).join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "thread-safe")]
+fn interner_scopes_shared() {
+    use std::thread;
+
+    // Each thread's scopes only drop what that same thread interned, and
+    // terms which escape them can still be sent anywhere.
+    let (here, there) = interner::with_interner(|| {
+        let here = Term::intern("__scoped_here");
+        let there = thread::spawn(|| {
+            interner::with_interner(|| Term::intern("__scoped_there"))
+        }).join().unwrap();
+        assert_eq!(interner::stats().unwrap().kept, "__scoped_there".len());
+        (here, there)
+    });
+    assert_eq!(interner::stats().unwrap().kept, "__scoped_here__scoped_there".len());
+    let here = thread::spawn(move || {
+        assert_eq!(here.as_str(), "__scoped_here");
+        here
+    }).join().unwrap();
+    assert_eq!(here, Term::intern("__scoped_here"));
+    drop((here, there));
+    assert_eq!(interner::stats().unwrap().kept, 0);
+}
+
+#[test]
+fn term_validation() {
+    assert_eq!(Term::try_new("foo").unwrap(), Term::intern("foo"));
+    assert_eq!(Term::try_new("_").unwrap().as_str(), "_");
+    assert_eq!(Term::try_new("fn").unwrap().as_str(), "fn");
+    assert_eq!(Term::try_new("\u{e9}t\u{e9}_2").unwrap().as_str(), "\u{e9}t\u{e9}_2");
+    assert_eq!(Term::try_new("r#x").unwrap(), Term::intern_raw("x"));
+    for bad in &["", "foo bar", "1x", "a-b", "'a", "r#self", "r#", "\u{200b}"] {
+        let err = Term::try_new(bad).unwrap_err();
+        assert_eq!(err.as_str(), *bad);
+        assert_eq!(err.to_string(), format!("`{}` is not a valid identifier", bad));
+    }
+
+    // The lexer accepts exactly the same identifiers.
+    for s in &["foo", "_x9", "\u{e9}t\u{e9}"] {
+        let tt = s.parse::<TokenStream>().unwrap().into_iter().next().unwrap();
+        assert_eq!(tt.to_string(), Term::try_new(s).unwrap().to_string());
+    }
+
+    // Lifetimes are still checked by their own rules.
+    assert_eq!(Lifetime::new("'a").as_str(), "'a");
+    assert_eq!(quote!('b).to_string(), "'b");
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "`foo bar` is not a valid identifier")]
+fn term_intern_invalid() {
+    Term::intern("foo bar");
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "`self` is not a valid raw identifier")]
+fn term_intern_raw_invalid() {
+    Term::intern_raw("self");
+}